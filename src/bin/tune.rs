@@ -0,0 +1,88 @@
+use std::{env, process, str::FromStr};
+
+use chess_engine::{tuner, EvalParams};
+
+const USAGE: &str = "usage: tune <positions.epd> [--params <start.txt>] [--out <tuned.txt>] \
+[--iterations <n>] [--k <scale>]";
+
+/// the value of a numeric argument, a usage error if it is not a number
+fn number<T: FromStr>(arg: &str, value: String) -> T {
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("{arg} expects a number, got {value}\n{USAGE}");
+        process::exit(1);
+    })
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut positions_path = None;
+    let mut params_path = None;
+    let mut out_path = String::from("tuned_params.txt");
+    let mut iterations = 100;
+    let mut k = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next().unwrap_or_else(|| {
+                eprintln!("{arg} expects a value\n{USAGE}");
+                process::exit(1);
+            })
+        };
+        match arg.as_str() {
+            "--params" => params_path = Some(value()),
+            "--out" => out_path = value(),
+            "--iterations" => iterations = number(&arg, value()),
+            "--k" => k = Some(number(&arg, value())),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ => positions_path = Some(arg),
+        }
+    }
+
+    let positions_path = positions_path.unwrap_or_else(|| {
+        eprintln!("{USAGE}");
+        process::exit(1);
+    });
+
+    let params = match params_path {
+        Some(path) => EvalParams::load(&path).unwrap_or_else(|err| {
+            eprintln!("{path}: {err}");
+            process::exit(1);
+        }),
+        None => EvalParams::default(),
+    };
+
+    let positions = tuner::load_positions(&positions_path).unwrap_or_else(|err| {
+        eprintln!("{positions_path}: {err}");
+        process::exit(1);
+    });
+    println!("loaded {} positions", positions.len());
+
+    let k = k.unwrap_or_else(|| tuner::find_k(&positions, &params));
+    println!(
+        "k = {k:.4}, initial error = {:.6}",
+        tuner::mean_squared_error(&positions, &params, k)
+    );
+
+    let tuned = tuner::tune(
+        &positions,
+        &params,
+        k,
+        iterations,
+        |iteration, error, params| {
+            println!("iteration {iteration}: error = {error:.6}");
+            // write after every pass so a long run can be stopped at any time
+            if let Err(err) = params.save(&out_path) {
+                eprintln!("{out_path}: {err}");
+            }
+        },
+    );
+
+    if let Err(err) = tuned.save(&out_path) {
+        eprintln!("{out_path}: {err}");
+        process::exit(1);
+    }
+    println!("tuned parameters written to {out_path}");
+}
@@ -23,11 +23,7 @@ lazy_static::lazy_static! {
         -40,-20,  0,  5,  5,  0,-20,-40,
         -50,-40,-30,-30,-30,-30,-40,-50,
     ];
-    pub static ref KNIGHT_VALUE_PER_SQUARE_BLACK: Vec<isize> = KNIGHT_VALUE_PER_SQUARE_WHITE
-        .iter()
-        .copied()
-        .rev()
-        .collect();
+    pub static ref KNIGHT_VALUE_PER_SQUARE_BLACK: Vec<isize> = flip_ranks(&KNIGHT_VALUE_PER_SQUARE_WHITE);
     pub static ref PAWN_VALUE_PER_SQUARE_WHITE: Vec<isize> = vec![
          0,  0,  0,  0,  0,  0,  0,  0,
         50, 50, 50, 50, 50, 50, 50, 50,
@@ -39,7 +35,7 @@ lazy_static::lazy_static! {
          0,  0,  0,  0,  0,  0,  0,  0
     ];
     pub static ref PAWN_VALUE_PER_SQUARE_BLACK: Vec<isize> =
-        PAWN_VALUE_PER_SQUARE_WHITE.iter().copied().rev().collect();
+        flip_ranks(&PAWN_VALUE_PER_SQUARE_WHITE);
     pub static ref BISHOP_VALUE_PER_SQUARE_WHITE: Vec<isize> = vec![
         -20,-10,-10,-10,-10,-10,-10,-20,
         -10,  0,  0,  0,  0,  0,  0,-10,
@@ -50,11 +46,7 @@ lazy_static::lazy_static! {
         -10,  5,  0,  0,  0,  0,  5,-10,
         -20,-10,-10,-10,-10,-10,-10,-20,
     ];
-    pub static ref BISHOP_VALUE_PER_SQUARE_BLACK: Vec<isize> = BISHOP_VALUE_PER_SQUARE_WHITE
-        .iter()
-        .copied()
-        .rev()
-        .collect();
+    pub static ref BISHOP_VALUE_PER_SQUARE_BLACK: Vec<isize> = flip_ranks(&BISHOP_VALUE_PER_SQUARE_WHITE);
     pub static ref KING_VALUE_PER_SQUARE_MIDDLE_GAME_WHITE: Vec<isize> = vec![
         -30, -40, -40, -50, -50, -40, -40, -30, -30, -40, -40, -50, -50, -40, -40, -30, -30, -40,
         -40, -50, -50, -40, -40, -30, -30, -40, -40, -50, -50, -40, -40, -30, -20, -30, -30, -40,
@@ -62,11 +54,7 @@ lazy_static::lazy_static! {
         30, 10, 0, 0, 10, 30, 20
     ];
     pub static ref KING_VALUE_PER_SQUARE_MIDDLE_GAME_BLACK: Vec<isize> =
-        KING_VALUE_PER_SQUARE_MIDDLE_GAME_WHITE
-            .iter()
-            .copied()
-            .rev()
-            .collect();
+        flip_ranks(&KING_VALUE_PER_SQUARE_MIDDLE_GAME_WHITE);
     pub static ref QUEEN_VALUE_PER_SQUARE_WHITE: Vec<isize> = vec![
         -20,-10,-10, -5, -5,-10,-10,-20,
         -10,  0,  0,  0,  0,  0,  0,-10,
@@ -78,7 +66,7 @@ lazy_static::lazy_static! {
         -20,-10,-10, -5, -5,-10,-10,-20
     ];
     pub static ref QUEEN_VALUE_PER_SQUARE_BLACK: Vec<isize> =
-        QUEEN_VALUE_PER_SQUARE_WHITE.iter().copied().rev().collect();
+        flip_ranks(&QUEEN_VALUE_PER_SQUARE_WHITE);
     pub static ref ROOK_VALUE_PER_SQUARE_WHITE: Vec<isize> = vec![
   0,  0,  0,  0,  0,  0,  0,  0,
   5, 10, 10, 10, 10, 10, 10,  5,
@@ -90,7 +78,7 @@ lazy_static::lazy_static! {
   0,  0,  0,  5,  5,  0,  0,  0
     ];
     pub static ref ROOK_VALUE_PER_SQUARE_BLACK: Vec<isize> =
-        ROOK_VALUE_PER_SQUARE_WHITE.iter().copied().rev().collect();
+        flip_ranks(&ROOK_VALUE_PER_SQUARE_WHITE);
 
     pub static ref KING_VALUE_PER_SQUARE_ENDGAME_WHITE: Vec<isize> = vec![
         -50,-40,-30,-20,-20,-30,-40,-50,
@@ -102,7 +90,7 @@ lazy_static::lazy_static! {
         -30,-30,  0,  0,  0,  0,-30,-30,
         -50,-30,-30,-30,-30,-30,-30,-50
 ];
-    pub static ref KING_VALUE_PER_SQUARE_ENDGAME_BLACK: Vec<isize> = KING_VALUE_PER_SQUARE_ENDGAME_WHITE.iter().copied().rev().collect();
+    pub static ref KING_VALUE_PER_SQUARE_ENDGAME_BLACK: Vec<isize> = flip_ranks(&KING_VALUE_PER_SQUARE_ENDGAME_WHITE);
     pub static ref FEN_STRING: Vec<String> = vec![
         String::from("r6r/1b2k1bq/8/8/7B/8/8/R3K2R b KQ - 3 2"),
         String::from("8/8/8/2k5/2pP4/8/B7/4K3 b - d3 0 3"),
@@ -129,14 +117,19 @@ lazy_static::lazy_static! {
         String::from("8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1"),
     ];
 }
-const fn reverse<const N: usize>(arr: [isize; N]) -> [isize; N] {
-    let mut reversed = [0; N];
+/// the table as seen from black, the ranks swapped and the files kept
+const fn flip_ranks_const(arr: [isize; 64]) -> [isize; 64] {
+    let mut flipped = [0; 64];
     let mut i = 0;
-    while i < N {
-        reversed[i] = arr[N - 1 - i];
+    while i < 64 {
+        flipped[i] = arr[i ^ 56];
         i += 1;
     }
-    reversed
+    flipped
+}
+
+fn flip_ranks(table: &[isize]) -> Vec<isize> {
+    (0..table.len()).map(|sq| table[sq ^ 56]).collect()
 }
 
 pub const KING_MIDDLE_WHITE: [isize; 64] = [
@@ -146,7 +139,7 @@ pub const KING_MIDDLE_WHITE: [isize; 64] = [
     10, 30, 20,
 ];
 
-pub const KING_MIDDLE_BLACK: [isize; 64] = reverse(KING_MIDDLE_WHITE);
+pub const KING_MIDDLE_BLACK: [isize; 64] = flip_ranks_const(KING_MIDDLE_WHITE);
 
 pub const KING_ENDGAME_WHITE: [isize; 64] = [
     -50, -40, -30, -20, -20, -30, -40, -50, -30, -20, -10, 0, 0, -10, -20, -30, -30, -10, 20, 30,
@@ -154,6 +147,6 @@ pub const KING_ENDGAME_WHITE: [isize; 64] = [
     -10, 20, 30, 30, 20, -10, -30, -30, -30, 0, 0, 0, 0, -30, -30, -50, -30, -30, -30, -30, -30,
     -30, -50,
 ];
-pub const KING_ENDGAME_BLACK: [isize; 64] = reverse(KING_ENDGAME_WHITE);
+pub const KING_ENDGAME_BLACK: [isize; 64] = flip_ranks_const(KING_ENDGAME_WHITE);
 
 pub const INITIAL_BOARD_VALUE: u16 = 23_900;
@@ -0,0 +1,166 @@
+use chess::{BitBoard, Board, Color, File, Piece, Rank, Square, EMPTY};
+
+use crate::eval::DARK_SQUARES;
+
+/// the scale factor of an ending without any drawing tendency
+pub const SCALE_FACTOR_NORMAL: isize = 64;
+pub const SCALE_FACTOR_DRAW: isize = 0;
+/// bishops of opposite colours and nothing else but pawns
+pub const SCALE_FACTOR_OPPOSITE_BISHOPS: isize = 16;
+
+fn pieces(board: &Board, piece: Piece, color: Color) -> BitBoard {
+    board.pieces(piece) & board.color_combined(color)
+}
+
+pub(crate) fn square_distance(a: Square, b: Square) -> usize {
+    let files = (a.get_file().to_index() as isize - b.get_file().to_index() as isize).abs();
+    let ranks = (a.get_rank().to_index() as isize - b.get_rank().to_index() as isize).abs();
+    files.max(ranks) as usize
+}
+
+/// Neither side can ever mate: bare kings, a single minor piece, or any number of bishops that
+/// all stand on squares of the same colour.
+pub fn insufficient_material(board: &Board) -> bool {
+    let heavy = board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    if heavy != EMPTY {
+        return false;
+    }
+
+    let knights = *board.pieces(Piece::Knight);
+    let bishops = *board.pieces(Piece::Bishop);
+    if (knights | bishops).popcnt() <= 1 {
+        return true;
+    }
+
+    let dark = DARK_SQUARES;
+    knights == EMPTY && (bishops & dark == EMPTY || bishops & !dark == EMPTY)
+}
+
+/// how much of the end game score `strong` can hope to convert, out of `SCALE_FACTOR_NORMAL`
+pub fn scale_factor(board: &Board, strong: Color) -> isize {
+    let weak = !strong;
+    let non_pawns = |color: Color| {
+        board.color_combined(color) & !board.pieces(Piece::Pawn) & !board.pieces(Piece::King)
+    };
+    let only = |color: Color, piece: Piece| {
+        let own = non_pawns(color);
+        own.popcnt() == 1 && own == pieces(board, piece, color)
+    };
+
+    // KR vs KR without pawns
+    if only(strong, Piece::Rook) && only(weak, Piece::Rook) && *board.pieces(Piece::Pawn) == EMPTY {
+        return SCALE_FACTOR_DRAW;
+    }
+
+    if only(strong, Piece::Bishop) {
+        if wrong_bishop(board, strong) {
+            return SCALE_FACTOR_DRAW;
+        }
+
+        let dark = DARK_SQUARES;
+        let strong_bishop = pieces(board, Piece::Bishop, strong);
+        let weak_bishop = pieces(board, Piece::Bishop, weak);
+        if only(weak, Piece::Bishop)
+            && (strong_bishop & dark == EMPTY) != (weak_bishop & dark == EMPTY)
+        {
+            return SCALE_FACTOR_OPPOSITE_BISHOPS;
+        }
+    }
+
+    SCALE_FACTOR_NORMAL
+}
+
+/// all pawns of `strong` are on one rook file, the bishop cannot cover the queening square and
+/// the defending king already stands next to it
+fn wrong_bishop(board: &Board, strong: Color) -> bool {
+    let pawns = pieces(board, Piece::Pawn, strong);
+    if pawns == EMPTY {
+        return false;
+    }
+
+    let file = match pawns.to_square().get_file() {
+        File::A => File::A,
+        File::H => File::H,
+        _ => return false,
+    };
+    if pawns.into_iter().any(|sq| sq.get_file() != file) {
+        return false;
+    }
+
+    let queening_rank = match strong {
+        Color::White => Rank::Eighth,
+        Color::Black => Rank::First,
+    };
+    let queening_square = Square::make_square(queening_rank, file);
+    let dark = DARK_SQUARES;
+    let bishop = pieces(board, Piece::Bishop, strong);
+    let queening_is_dark = BitBoard::from_square(queening_square) & dark != EMPTY;
+    let bishop_is_dark = bishop & dark != EMPTY;
+    if queening_is_dark == bishop_is_dark {
+        return false;
+    }
+
+    square_distance(board.king_square(!strong), queening_square) <= 1
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chess::{Board, Color};
+
+    use super::*;
+
+    #[test]
+    fn detects_insufficient_material() {
+        let draws = [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3BK3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3NK3 b - - 0 1",
+            "2b1k3/8/8/8/8/8/8/3BK3 w - - 0 1",
+        ];
+        for fen in draws {
+            assert!(
+                insufficient_material(&Board::from_str(fen).unwrap()),
+                "{fen}"
+            );
+        }
+
+        let not_draws = [
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+            "3bk3/8/8/8/8/8/8/3BK3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2NBK3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3RK3 w - - 0 1",
+        ];
+        for fen in not_draws {
+            assert!(
+                !insufficient_material(&Board::from_str(fen).unwrap()),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn drawish_endings_are_scaled() {
+        let scale = |fen: &str, strong| scale_factor(&Board::from_str(fen).unwrap(), strong);
+
+        // light squared bishop cannot help the h-pawn promote on the dark h8
+        assert_eq!(
+            scale("7k/8/8/8/8/3B3P/8/4K3 w - - 0 1", Color::White),
+            SCALE_FACTOR_DRAW
+        );
+        // with the right bishop it is a win
+        assert_eq!(
+            scale("7k/8/8/8/8/4B2P/8/4K3 w - - 0 1", Color::White),
+            SCALE_FACTOR_NORMAL
+        );
+        assert_eq!(
+            scale("4k3/8/8/8/8/8/8/R3K2r w - - 0 1", Color::White),
+            SCALE_FACTOR_DRAW
+        );
+        assert_eq!(
+            scale("4k3/3pb3/8/8/8/8/3PP3/3BK3 w - - 0 1", Color::White),
+            SCALE_FACTOR_OPPOSITE_BISHOPS
+        );
+    }
+}
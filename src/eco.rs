@@ -0,0 +1,179 @@
+//! ECO classification: the code and name of the opening a game is in, from a table of the lines
+//! that define each opening.
+
+use std::collections::HashMap;
+
+use chess::{Board, ChessMove};
+
+use crate::{
+    pgn::{parse_games, PgnError},
+    trie::Trie,
+};
+
+lazy_static::lazy_static! {
+    /// the table that comes with the engine, see `eco.pgn`
+    static ref BUNDLED: EcoTable =
+        EcoTable::from_pgn(include_str!("eco.pgn")).expect("the bundled ECO table is valid");
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EcoOpening {
+    /// `C60` and so on
+    pub eco: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EcoTable {
+    openings: Vec<EcoOpening>,
+    /// the defining lines, the payload of the last move of each is its index in `openings`
+    lines: Trie<ChessMove, Option<usize>>,
+    /// the positions the lines end in, to classify transpositions
+    positions: HashMap<u64, usize>,
+}
+
+impl EcoTable {
+    pub fn bundled() -> &'static EcoTable {
+        &BUNDLED
+    }
+
+    /// Builds a table from PGN games with an `ECO` and an `Opening` tag each, the `Variation`
+    /// tag is added to the name when there is one. A line defined twice keeps the first name.
+    pub fn from_pgn(pgn: &str) -> Result<Self, PgnError> {
+        let mut table = Self::default();
+        for game in parse_games(pgn)? {
+            let (Some(eco), Some(opening)) = (game.header("ECO"), game.header("Opening")) else {
+                continue;
+            };
+            let name = match game.header("Variation") {
+                Some(variation) => format!("{opening}: {variation}"),
+                None => opening.to_string(),
+            };
+            let line = game.mainline();
+            let board = line
+                .iter()
+                .fold(game.start_board()?, |board, mov| board.make_move_new(*mov));
+
+            let index = table.openings.len();
+            table.openings.push(EcoOpening {
+                eco: eco.to_string(),
+                name,
+            });
+            table.lines.insert(&line);
+            if let Some(node) = table.lines.node_mut(&line) {
+                node.payload_mut().get_or_insert(index);
+            }
+            table.positions.entry(board.get_hash()).or_insert(index);
+        }
+        Ok(table)
+    }
+
+    pub fn len(&self) -> usize {
+        self.openings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.openings.is_empty()
+    }
+
+    /// The most specific opening of the game `moves` from the start position: the last of its
+    /// positions that ends a line of the table, whatever the move order that led there. It
+    /// stops at the first illegal move.
+    pub fn classify(&self, moves: &[ChessMove]) -> Option<&EcoOpening> {
+        let mut board = Board::default();
+        let mut node = Some(self.lines.root());
+        let mut found = None;
+        for mov in moves {
+            if !board.legal(*mov) {
+                break;
+            }
+            board = board.make_move_new(*mov);
+            node = node.and_then(|node| node.children().get(mov));
+            let index = node
+                .and_then(|node| *node.payload())
+                .or_else(|| self.positions.get(&board.get_hash()).copied());
+            found = index.or(found);
+        }
+        found.map(|index| &self.openings[index])
+    }
+
+    /// the opening that ends in `board` exactly, for a game whose moves are not known
+    pub fn classify_board(&self, board: &Board) -> Option<&EcoOpening> {
+        self.positions
+            .get(&board.get_hash())
+            .map(|index| &self.openings[*index])
+    }
+
+    /// `C60 Ruy Lopez` for the game `moves`, see `classify`
+    pub fn opening_name(&self, moves: &[ChessMove]) -> Option<String> {
+        self.classify(moves)
+            .map(|opening| format!("{} {}", opening.eco, opening.name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chess::Board;
+
+    use super::*;
+    use crate::test_util::line;
+
+    #[test]
+    fn bundled_table() {
+        let table = EcoTable::bundled();
+        assert!(table.len() > 100);
+
+        let ruy = line(&["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"]);
+        assert_eq!(table.opening_name(&ruy).unwrap(), "C60 Ruy Lopez");
+        // moves past the table keep the last opening
+        let morphy = line(&[
+            "e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "b5a4", "d7d6",
+        ]);
+        assert_eq!(
+            table.classify(&morphy).unwrap().name,
+            "Ruy Lopez: Morphy Defense"
+        );
+        assert_eq!(table.classify(&[]), None);
+        // every first move has a name
+        for mov in chess::MoveGen::new_legal(&Board::default()) {
+            assert!(table.classify(&[mov]).is_some(), "{mov}");
+        }
+
+        // the Four Knights reached through the Vienna
+        let vienna = line(&["e2e4", "e7e5", "b1c3", "g8f6", "g1f3", "b8c6"]);
+        assert_eq!(table.classify(&vienna).unwrap().eco, "C47");
+
+        let najdorf = line(&[
+            "e2e4", "c7c5", "g1f3", "d7d6", "d2d4", "c5d4", "f3d4", "g8f6", "b1c3", "a7a6",
+        ]);
+        assert_eq!(table.classify(&najdorf).unwrap().eco, "B90");
+
+        let board = line(&["d2d4", "g8f6", "c2c4", "e7e6", "b1c3", "f8b4"])
+            .into_iter()
+            .fold(Board::default(), |board, mov| board.make_move_new(mov));
+        assert_eq!(
+            table.classify_board(&board).unwrap().name,
+            "Nimzo-Indian Defense"
+        );
+    }
+
+    #[test]
+    fn tables_from_pgn() {
+        let table = EcoTable::from_pgn(
+            "[ECO \"C42\"]\n[Opening \"Petrov's Defense\"]\n[Variation \"Main Line\"]\n\n\
+             1. e4 e5 2. Nf3 Nf6 3. Nxe5 d6 *\n\n\
+             [Event \"no tags\"]\n\n1. e4 *",
+        )
+        .unwrap();
+        assert_eq!(table.len(), 1);
+        let petrov = line(&["e2e4", "e7e5", "g1f3", "g8f6", "f3e5", "d7d6"]);
+        assert_eq!(
+            table.opening_name(&petrov).unwrap(),
+            "C42 Petrov's Defense: Main Line"
+        );
+        // not there yet
+        assert_eq!(table.classify(&petrov[..4]), None);
+        // an illegal move ends the game
+        assert_eq!(table.classify(&line(&["e2e4", "e2e4"])), None);
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+
+use chess::{BitBoard, Board, Color, File, Piece, Rank, Square, EMPTY};
+
+use crate::{draw::square_distance, eval::DARK_SQUARES, eval_params::EvalParams, kpk};
+
+/// added to the score of endings that are won with correct play
+pub const KNOWN_WIN: isize = 10_000;
+
+/// evaluates an ending for the `strong` side, from the point of view of `strong`
+pub type EndgameFn = fn(&Board, Color, &EvalParams) -> isize;
+
+lazy_static::lazy_static! {
+    /// every specialised evaluator, keyed by the material of the strong side followed by the
+    /// material of the weak side
+    static ref ENDGAMES: HashMap<&'static str, EndgameFn> = {
+        let mut endgames: HashMap<&'static str, EndgameFn> = HashMap::new();
+        endgames.insert("KBNK", kbnk);
+        endgames.insert("KQKR", kqkr);
+        endgames.insert("KRKP", krkp);
+        endgames.insert("KPK", kpk);
+        endgames
+    };
+}
+
+/// pieces in the order they are written in a material key
+const KEY_PIECES: [(Piece, char); 5] = [
+    (Piece::Queen, 'Q'),
+    (Piece::Rook, 'R'),
+    (Piece::Bishop, 'B'),
+    (Piece::Knight, 'N'),
+    (Piece::Pawn, 'P'),
+];
+
+fn pieces(board: &Board, piece: Piece, color: Color) -> BitBoard {
+    board.pieces(piece) & board.color_combined(color)
+}
+
+/// the pieces of one side, e.g. `KRP`
+pub(crate) fn side_key(board: &Board, color: Color) -> String {
+    let mut key = String::from("K");
+    for (piece, c) in KEY_PIECES {
+        for _ in 0..pieces(board, piece, color).popcnt() {
+            key.push(c);
+        }
+    }
+    key
+}
+
+/// the material signature with `strong` first, e.g. `KBNK`
+pub fn material_key(board: &Board, strong: Color) -> String {
+    side_key(board, strong) + &side_key(board, !strong)
+}
+
+/// 0 in the centre, 90 in the corners
+fn push_to_edge(sq: Square) -> isize {
+    let file = sq.get_file().to_index() as isize;
+    let rank = sq.get_rank().to_index() as isize;
+    let fd = file.min(7 - file);
+    let rd = rank.min(7 - rank);
+    90 - (7 * fd * fd / 2 + 7 * rd * rd / 2)
+}
+
+/// highest in the dark corners a1 and h8
+fn push_to_corner(sq: Square) -> isize {
+    (7 - sq.get_rank().to_index() as isize - sq.get_file().to_index() as isize).abs()
+}
+
+/// rewards bringing the kings close together
+fn push_close(a: Square, b: Square) -> isize {
+    140 - 20 * square_distance(a, b) as isize
+}
+
+/// the square as seen from `color`, so black's pieces move up the board like white's
+fn relative_square(color: Color, sq: Square) -> Square {
+    match color {
+        Color::White => sq,
+        Color::Black => Square::make_square(
+            Rank::from_index(7 - sq.get_rank().to_index()),
+            sq.get_file(),
+        ),
+    }
+}
+
+fn non_pawn_material(board: &Board, color: Color, params: &EvalParams) -> isize {
+    [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]
+        .iter()
+        .map(|piece| pieces(board, *piece, color).popcnt() as isize * params.piece_value(*piece))
+        .sum()
+}
+
+/// Lone king against enough material to mate: drive the king to the edge and bring our own king
+/// closer.
+fn kxk(board: &Board, strong: Color, params: &EvalParams) -> isize {
+    let strong_king = board.king_square(strong);
+    let weak_king = board.king_square(!strong);
+    let pawns = pieces(board, Piece::Pawn, strong).popcnt() as isize;
+    let result = non_pawn_material(board, strong, params)
+        + pawns * params.piece_value(Piece::Pawn)
+        + push_to_edge(weak_king)
+        + push_close(strong_king, weak_king);
+
+    let bishops = pieces(board, Piece::Bishop, strong);
+    let bishops_of_both_colours =
+        bishops & DARK_SQUARES != EMPTY && bishops & !DARK_SQUARES != EMPTY;
+    let heavy = pieces(board, Piece::Queen, strong) | pieces(board, Piece::Rook, strong);
+    if heavy != EMPTY
+        || bishops_of_both_colours
+        || (bishops != EMPTY && pieces(board, Piece::Knight, strong) != EMPTY)
+    {
+        result + KNOWN_WIN
+    } else {
+        result
+    }
+}
+
+/// Bishop and knight: the king can only be mated in a corner of the bishop's colour.
+fn kbnk(board: &Board, strong: Color, params: &EvalParams) -> isize {
+    let mut strong_king = board.king_square(strong);
+    let mut weak_king = board.king_square(!strong);
+    let bishop = pieces(board, Piece::Bishop, strong);
+
+    // push_to_corner prefers the dark corners, mirror the board for a light squared bishop
+    if bishop & DARK_SQUARES == EMPTY {
+        let mirror = |sq: Square| {
+            Square::make_square(
+                sq.get_rank(),
+                File::from_index(7 - sq.get_file().to_index()),
+            )
+        };
+        strong_king = mirror(strong_king);
+        weak_king = mirror(weak_king);
+    }
+
+    KNOWN_WIN
+        + params.piece_value(Piece::Bishop)
+        + params.piece_value(Piece::Knight)
+        + push_close(strong_king, weak_king)
+        + 60 * push_to_corner(weak_king)
+}
+
+/// Queen against rook is a win, it only needs the king pushed to the edge.
+fn kqkr(board: &Board, strong: Color, params: &EvalParams) -> isize {
+    let strong_king = board.king_square(strong);
+    let weak_king = board.king_square(!strong);
+    params.piece_value(Piece::Queen) - params.piece_value(Piece::Rook)
+        + push_to_edge(weak_king)
+        + push_close(strong_king, weak_king)
+}
+
+/// Rook against pawn, mostly a win unless the pawn is far advanced and supported by its king.
+fn krkp(board: &Board, strong: Color, params: &EvalParams) -> isize {
+    let weak = !strong;
+    let strong_king = relative_square(strong, board.king_square(strong));
+    let weak_king = relative_square(strong, board.king_square(weak));
+    let rook = relative_square(strong, pieces(board, Piece::Rook, strong).to_square());
+    let pawn = relative_square(strong, pieces(board, Piece::Pawn, weak).to_square());
+    let queening = Square::make_square(Rank::First, pawn.get_file());
+    let rook_value = params.piece_value(Piece::Rook);
+    let weak_to_move = (board.side_to_move() == weak) as usize;
+    let strong_to_move = (board.side_to_move() == strong) as usize;
+
+    // the pawn moves down the board after the flip, so one step ahead of it is one rank lower
+    let in_front = pawn.down().unwrap_or(pawn);
+
+    if strong_king.get_file() == pawn.get_file() && strong_king.get_rank() < pawn.get_rank() {
+        // our king blocks the pawn
+        rook_value - square_distance(strong_king, pawn) as isize
+    } else if square_distance(weak_king, pawn) >= 3 + weak_to_move
+        && square_distance(weak_king, rook) >= 3
+    {
+        // their king is too far from both the pawn and the rook
+        rook_value - square_distance(strong_king, pawn) as isize
+    } else if weak_king.get_rank() <= Rank::Third
+        && square_distance(weak_king, pawn) == 1
+        && strong_king.get_rank() >= Rank::Fourth
+        && square_distance(strong_king, pawn) > 2 + strong_to_move
+    {
+        // far advanced pawn supported by its king, drawish
+        80 - 8 * square_distance(strong_king, pawn) as isize
+    } else {
+        200 - 8
+            * (square_distance(strong_king, in_front) as isize
+                - square_distance(weak_king, in_front) as isize
+                - square_distance(pawn, queening) as isize)
+    }
+}
+
+/// King and pawn against king is looked up in the bitbase, a draw is exactly zero.
+fn kpk(board: &Board, strong: Color, params: &EvalParams) -> isize {
+    let pawn = pieces(board, Piece::Pawn, strong).to_square();
+    let wins = kpk::probe(
+        strong,
+        board.king_square(strong),
+        pawn,
+        board.king_square(!strong),
+        board.side_to_move(),
+    );
+    if wins {
+        KNOWN_WIN
+            + params.piece_value(Piece::Pawn)
+            + relative_square(strong, pawn).get_rank().to_index() as isize
+    } else {
+        0
+    }
+}
+
+/// the specialised evaluator for the material on the board, if there is one, with its key and
+/// the strong side
+pub fn find(board: &Board) -> Option<(String, Color, EndgameFn)> {
+    for strong in [Color::White, Color::Black] {
+        let key = material_key(board, strong);
+        if let Some(eval) = ENDGAMES.get(key.as_str()) {
+            return Some((key, strong, *eval));
+        }
+    }
+
+    for strong in [Color::White, Color::Black] {
+        let weak_is_bare = board.color_combined(!strong).popcnt() == 1;
+        let can_mate =
+            board.color_combined(strong) & !board.pieces(Piece::King) & !board.pieces(Piece::Pawn)
+                != EMPTY;
+        if weak_is_bare && can_mate {
+            return Some((String::from("KXK"), strong, kxk as EndgameFn));
+        }
+    }
+    None
+}
+
+/// the value of the position from white's point of view when a specialised evaluator knows the
+/// ending
+pub fn evaluate(board: &Board, params: &EvalParams) -> Option<isize> {
+    let (_, strong, eval) = find(board)?;
+    let value = eval(board, strong, params);
+    match strong {
+        Color::White => Some(value),
+        Color::Black => Some(-value),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chess::Board;
+
+    use super::*;
+
+    fn value(fen: &str) -> isize {
+        evaluate(&Board::from_str(fen).unwrap(), &EvalParams::default()).unwrap()
+    }
+
+    #[test]
+    fn selects_evaluator_by_material() {
+        let key =
+            |fen: &str| find(&Board::from_str(fen).unwrap()).map(|(key, strong, _)| (key, strong));
+        assert_eq!(
+            key("4k3/8/8/8/8/8/8/2BNK3 w - - 0 1"),
+            Some(("KBNK".to_string(), Color::White))
+        );
+        assert_eq!(
+            key("4k3/8/8/8/8/8/8/r3K3 w - - 0 1"),
+            Some(("KXK".to_string(), Color::Black))
+        );
+        assert_eq!(
+            key("4k3/8/8/8/8/8/4p3/R3K3 w - - 0 1"),
+            Some(("KRKP".to_string(), Color::White))
+        );
+        assert_eq!(key("4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 1"), None);
+    }
+
+    #[test]
+    fn kxk_prefers_the_king_on_the_edge() {
+        let centre = value("8/8/8/4k3/8/8/8/R3K3 w - - 0 1");
+        let edge = value("8/8/8/8/8/8/8/R3K2k w - - 0 1");
+        assert!(edge > centre);
+        assert!(centre > KNOWN_WIN);
+    }
+
+    #[test]
+    fn kbnk_drives_to_the_bishop_corner() {
+        // dark squared bishop on c1, mate happens on a1 or h8
+        let right_corner = value("7k/8/8/8/8/8/8/2B1K1N1 w - - 0 1");
+        let wrong_corner = value("k7/8/8/8/8/8/8/2B1K1N1 w - - 0 1");
+        assert!(right_corner > wrong_corner);
+
+        // black to be mated with a light squared bishop wants a8 or h1
+        let right_corner = value("k7/8/8/8/8/8/8/3BK1N1 w - - 0 1");
+        let wrong_corner = value("7k/8/8/8/8/8/8/3BK1N1 w - - 0 1");
+        assert!(right_corner > wrong_corner);
+        // and the king next to the cornered one is better than a distant one
+        let close = value("k7/8/1K6/8/8/8/8/3B2N1 w - - 0 1");
+        let distant = value("k7/8/6K1/8/8/8/8/3B2N1 w - - 0 1");
+        assert!(close > distant);
+    }
+
+    #[test]
+    fn krkp_drawish_when_pawn_is_supported() {
+        let won = value("8/8/8/8/8/8/4p3/R3K2k w - - 0 1");
+        let drawish = value("R7/8/8/K7/8/8/4pk2/8 w - - 0 1");
+        assert!(won > drawish);
+        assert!(won > 400);
+    }
+
+    #[test]
+    fn kpk_rule_of_the_square() {
+        assert!(value("7k/8/8/8/P7/8/8/4K3 w - - 0 1") > KNOWN_WIN);
+        assert!(value("4k3/8/8/8/P7/8/8/4K3 w - - 0 1") < KNOWN_WIN);
+        // black pawn, mirrored
+        assert!(value("4k3/8/8/p7/8/8/8/7K b - - 0 1") < -KNOWN_WIN);
+    }
+}
@@ -1,17 +1,30 @@
 use std::{
     cell::RefCell,
-    cmp::Ordering,
+    cmp::{Ordering, Reverse},
     rc::Rc,
     str::FromStr,
     sync::{Arc, Mutex, RwLock},
     time::Instant,
 };
 
-use chess::{BitBoard, Board, ChessMove, Color, MoveGen, Piece, Square};
+use chess::{BitBoard, Board, ChessMove, Color, MoveGen, Piece, Square, EMPTY};
+use rand::{rngs::StdRng, SeedableRng};
 
 use crate::{
-    eval::Evaluation, evaluation_value::EvaluationValue, game_phase::GamePhases,
-    game_state::GameState, BoardMaterial, OpeningDatabase,
+    eco::{EcoOpening, EcoTable},
+    endgame,
+    eval::Evaluation,
+    eval_params::EvalParams,
+    eval_trace::EvalTrace,
+    evaluation_value::EvaluationValue,
+    game_phase::{self, GamePhases},
+    game_state::GameState,
+    kpk,
+    nnue::{Accumulator, Network},
+    polyglot::PolyglotBook,
+    see::see,
+    syzygy::Tablebases,
+    BoardMaterial, BookCursor, BookPolicy, OpeningDatabase,
 };
 
 enum MoveType {
@@ -33,13 +46,41 @@ impl PieceOnBoardExt for Board {
     }
 }
 
+/// which evaluation is used at the leaves of the search
+#[derive(Clone)]
+pub enum Evaluator {
+    Classic,
+    Nnue(Arc<Network>),
+}
+
 pub struct Engine {
     board: Board,
     best_move: Option<ChessMove>,
     side_playing: chess::Color,
     board_history: Vec<u64>,
     game_state: GameState,
-    opening_database: OpeningDatabase,
+    /// shared by every engine of the session, it is never changed while a game is played
+    opening_database: Arc<OpeningDatabase>,
+    /// the line played from the start position, `None` for a game set up from another position
+    book_cursor: Option<BookCursor>,
+    eval_params: EvalParams,
+    evaluator: Evaluator,
+    /// NNUE accumulators of the positions on the current search path
+    accumulators: Vec<Accumulator>,
+    tablebases: Option<Arc<Tablebases>>,
+    /// centipawns the engine gives up to avoid a draw, negative to seek one
+    contempt: isize,
+    /// whether the engine plays from a book at all
+    own_book: bool,
+    /// a Polyglot book takes the place of the opening database when one is loaded
+    polyglot_book: Option<Arc<PolyglotBook>>,
+    book_policy: BookPolicy,
+    /// drives the random book policies, seed it for reproducible matches
+    book_rng: StdRng,
+    /// whether the last move chosen came from a book rather than a search
+    book_move: bool,
+    /// positions found in the tablebases during the current search
+    tb_hits: u64,
 }
 
 impl FromStr for Engine {
@@ -48,13 +89,33 @@ impl FromStr for Engine {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let board = Board::from_str(s)?;
         let board_history = vec![board.get_hash()];
+        let mut game_state = GameState::new();
+        // the chess crate ignores the clocks, a missing or broken field counts as zero
+        let halfmove_clock = s
+            .split_whitespace()
+            .nth(4)
+            .and_then(|clock| clock.parse().ok())
+            .unwrap_or(0);
+        game_state.set_halfmove_clock(halfmove_clock);
         Ok(Self {
             board,
             best_move: None,
             side_playing: board.side_to_move(),
             board_history,
-            game_state: GameState::new(),
-            opening_database: OpeningDatabase::new(),
+            game_state,
+            opening_database: Arc::default(),
+            book_cursor: (board == Board::default()).then(BookCursor::new),
+            eval_params: EvalParams::default(),
+            evaluator: Evaluator::Classic,
+            accumulators: Vec::new(),
+            tablebases: None,
+            contempt: 0,
+            own_book: true,
+            polyglot_book: None,
+            book_policy: BookPolicy::default(),
+            book_rng: StdRng::from_entropy(),
+            book_move: false,
+            tb_hits: 0,
         })
     }
 }
@@ -68,20 +129,109 @@ impl Engine {
         &self.board_history
     }
 
-    pub fn add_opening_db(&mut self, op_db: OpeningDatabase) {
+    pub fn add_opening_db(&mut self, op_db: Arc<OpeningDatabase>) {
         self.opening_database = op_db;
     }
 
+    pub fn book_cursor(&self) -> Option<&BookCursor> {
+        self.book_cursor.as_ref()
+    }
+
+    /// the ECO opening of the game, only the current position counts for a game set up from a FEN
+    pub fn opening(&self) -> Option<&'static EcoOpening> {
+        let table = EcoTable::bundled();
+        match &self.book_cursor {
+            Some(cursor) => table.classify(cursor.path()),
+            None => table.classify_board(&self.board),
+        }
+    }
+
+    pub fn set_eval_params(&mut self, params: EvalParams) {
+        self.eval_params = params;
+    }
+
+    pub fn set_evaluator(&mut self, evaluator: Evaluator) {
+        self.evaluator = evaluator;
+    }
+
+    pub fn set_own_book(&mut self, own_book: bool) {
+        self.own_book = own_book;
+    }
+
+    pub fn set_polyglot_book(&mut self, book: Option<Arc<PolyglotBook>>) {
+        self.polyglot_book = book;
+    }
+
+    pub fn set_book_policy(&mut self, policy: BookPolicy) {
+        self.book_policy = policy;
+    }
+
+    pub fn book_rng(&self) -> &StdRng {
+        &self.book_rng
+    }
+
+    pub fn set_book_rng(&mut self, rng: StdRng) {
+        self.book_rng = rng;
+    }
+
+    pub fn is_book_move(&self) -> bool {
+        self.book_move
+    }
+
+    pub fn set_contempt(&mut self, contempt: isize) {
+        self.contempt = contempt;
+    }
+
+    /// what a draw is worth to the side the engine plays
+    fn draw_score(&self) -> isize {
+        -self.contempt
+    }
+
+    pub fn set_tablebases(&mut self, tablebases: Option<Arc<Tablebases>>) {
+        self.tablebases = tablebases;
+    }
+
+    /// keeps the NNUE accumulators in step with the moves made during the search, does nothing
+    /// for the classic evaluation
+    fn push_move(&mut self, board: &Board, mov: ChessMove) {
+        if let Evaluator::Nnue(net) = &self.evaluator {
+            let acc = self
+                .accumulators
+                .last()
+                .expect("search starts from a refreshed accumulator")
+                .apply_move(net, board, mov);
+            self.accumulators.push(acc);
+        }
+    }
+
+    fn pop_move(&mut self) {
+        if let Evaluator::Nnue(_) = &self.evaluator {
+            self.accumulators.pop();
+        }
+    }
+
     pub fn new() -> Self {
         let board = Board::default();
         let board_history = vec![board.get_hash()];
         Self {
-            opening_database: OpeningDatabase::new(),
+            opening_database: Arc::default(),
+            book_cursor: Some(BookCursor::new()),
             board,
             best_move: None,
             side_playing: chess::Color::White,
             board_history,
             game_state: GameState::new(),
+            eval_params: EvalParams::default(),
+            evaluator: Evaluator::Classic,
+            accumulators: Vec::new(),
+            tablebases: None,
+            contempt: 0,
+            own_book: true,
+            polyglot_book: None,
+            book_policy: BookPolicy::default(),
+            book_rng: StdRng::from_entropy(),
+            book_move: false,
+            tb_hits: 0,
         }
     }
 
@@ -89,48 +239,33 @@ impl Engine {
         MoveGen::new_legal(board).collect()
     }
 
-    /// Sorts moves based on if the move captures a piece or does a promotion
-    /// if a move is a capture or promotion it will be sent higher in the list
-    /// this will help the `alpha-beta` pruning
+    /// Sorts the moves most likely to be good first, which helps the `alpha-beta` pruning:
+    /// captures that win material by static exchange, promotions, quiet moves and last the
+    /// captures that lose material. In endings checks go before everything else.
     fn sort_moves_in_place(&self, board: &Board, moves: &mut [ChessMove], game_state: &GameState) {
-        moves.sort_by(|d: &ChessMove, other: &ChessMove| {
-            if game_state.game_phases() == &GamePhases::EndGame {
-                let other_board = board.make_move_new(*other);
-                if other_board.checkers() != &BitBoard(0) {
-                    return Ordering::Greater;
-                }
-                let curr_board = board.make_move_new(*d);
-                if curr_board.checkers() != &BitBoard(0) {
-                    return Ordering::Less;
+        let phase = match game_state.eval_state() {
+            Some(state) => state.phase(),
+            None => game_phase::phase(board),
+        };
+        let endgame = GamePhases::from_phase(phase).is_end();
+        moves.sort_by_cached_key(|mov| {
+            let gives_check = endgame && *board.make_move_new(*mov).checkers() != EMPTY;
+            let en_passant = board.piece_on(mov.get_source()) == Some(Piece::Pawn)
+                && mov.get_source().get_file() != mov.get_dest().get_file();
+            let is_capture = board.piece_on(mov.get_dest()).is_some() || en_passant;
+            let order = if is_capture {
+                let exchange = see(board, *mov);
+                if exchange >= 0 {
+                    20_000 + exchange
+                } else {
+                    exchange
                 }
-            }
-
-            let square: Square = d.get_dest();
-            let piece = board.piece_on(square);
-
-            let square_other: Square = other.get_dest();
-            let piece_other = board.piece_on(square_other);
-
-            if piece.is_some() && piece_other.is_some() {
-                return Ordering::Equal;
-            }
-
-            if piece.is_some() {
-                return Ordering::Less;
-            }
-
-            if piece_other.is_some() {
-                return Ordering::Greater;
-            }
-
-            if d.get_promotion().is_some() {
-                return Ordering::Less;
-            }
-            if other.get_promotion().is_some() {
-                return Ordering::Greater;
-            }
-
-            Ordering::Equal
+            } else if mov.get_promotion().is_some() {
+                10_000
+            } else {
+                0
+            };
+            (Reverse(gives_check), Reverse(order))
         });
     }
 
@@ -145,28 +280,46 @@ impl Engine {
 
     pub fn play_move(&mut self, mov: ChessMove) {
         let board = self.board.make_move_new(mov);
+        self.game_state
+            .make_move(&self.board, mov, &self.eval_params);
         self.board = board;
         self.board_history.push(board.get_hash());
-        self.game_state.set_lastmove(mov);
         self.side_playing = self.board.side_to_move();
+        if let Some(cursor) = self.book_cursor.as_mut() {
+            cursor.push(mov);
+        }
     }
 
-    pub fn search(&mut self, depth: usize, game_state: GameState) -> EvaluationValue {
-        let legal_moves = self.gen_legal_moves(&self.board);
+    pub fn search(&mut self, depth: usize, mut game_state: GameState) -> EvaluationValue {
+        let legal_moves = self
+            .tablebase_root_moves()
+            .unwrap_or_else(|| self.gen_legal_moves(&self.board));
         let mut best_eval = EvaluationValue::CheckMate(false);
+        game_state.refresh_eval_state(&self.board, &self.eval_params);
+
+        self.accumulators.clear();
+        if let Evaluator::Nnue(net) = &self.evaluator {
+            self.accumulators
+                .push(Accumulator::refresh(net, &self.board));
+        }
 
         for m in legal_moves.iter() {
             // make the move
             let next_board = self.board.make_move_new(*m);
             self.game_state.set_lastmove(*m);
+            let board = self.board;
+            let mut child_state = game_state;
+            child_state.make_move(&board, *m, &self.eval_params);
+            self.push_move(&board, *m);
             let next_eval = self.search_alpha_beta(
                 depth,
                 &next_board,
                 EvaluationValue::CheckMate(false),
                 EvaluationValue::CheckMate(true),
                 false,
-                game_state,
+                child_state,
             );
+            self.pop_move();
 
             if next_eval > best_eval || self.best_move.is_none() {
                 best_eval = next_eval;
@@ -176,41 +329,45 @@ impl Engine {
         best_eval
     }
 
-    /// TODO: make this better,
-    /// BUG: the bot only plays the lines it can find, even if the other player has gone out of the line
+    /// A book move of the current position picked by the book policy. The book is keyed by
+    /// position, so it still answers after a transposition or from a position set up with a FEN.
     fn get_best_move_from_opening_database(&mut self) -> bool {
-        if self.game_state.last_move().is_some() {
-            match !self
-                .opening_database
-                .choose_opening_move(self.game_state.last_move().unwrap())
-            {
-                true => {
-                    self.game_state.set_gamephases_middlegame();
-                    return false;
-                }
-                false => (),
+        let moves = self.opening_database.moves(&self.board);
+        match self.book_policy.choose(&moves, &mut self.book_rng) {
+            Some(mov) => {
+                let _ = self.best_move.insert(mov);
+                true
             }
+            None => false,
         }
+    }
 
-        if let Some(x) = self.opening_database.root().childern().keys().next() {
-            let _ = self.best_move.insert(*x);
-            self.opening_database
-                .choose_opening_move(self.best_move.expect("SETTING IT RIGHT BEFORE THIS"));
-            true
-        } else {
-            false
+    /// a move the Polyglot book has for the current position, picked by the book policy
+    fn get_best_move_from_polyglot_book(&mut self) -> bool {
+        let Some(book) = &self.polyglot_book else {
+            return false;
+        };
+        let moves = book.moves(&self.board);
+        match self.book_policy.choose_weighted(&moves, &mut self.book_rng) {
+            Some(mov) => {
+                let _ = self.best_move.insert(mov);
+                true
+            }
+            None => false,
         }
     }
 
     pub fn search_iterative_deeping(&mut self, search_cancel_time: Instant) -> EvaluationValue {
-        let mut game_state = self.game_state.game_phases();
-        if !self.opening_database.is_end()
-            && *game_state == GamePhases::Opening
-            && self.get_best_move_from_opening_database()
-        {
+        self.book_move = self.own_book
+            && match self.polyglot_book {
+                Some(_) => self.get_best_move_from_polyglot_book(),
+                None => self.get_best_move_from_opening_database(),
+            };
+        if self.book_move {
             return 0.into();
         }
         println!("info starting Iterative Deepinnn");
+        self.tb_hits = 0;
         let mut best_eval = EvaluationValue::CheckMate(false);
         for x in 1..usize::MAX {
             let now = Instant::now();
@@ -218,8 +375,9 @@ impl Engine {
             if now >= search_cancel_time {
                 break;
             }
-            println!("info depth {}", x);
             let eval = self.search(x, game_state);
+            // reported once the depth is done so the hits include it
+            println!("info depth {} tbhits {}", x, self.tb_hits);
             best_eval = best_eval.max(eval);
         }
         best_eval
@@ -232,12 +390,24 @@ impl Engine {
         mut alpha: EvaluationValue,
         mut beta: EvaluationValue,
         is_maximizing: bool,
-        mut game_state: GameState,
+        game_state: GameState,
     ) -> EvaluationValue {
+        // the tables assume the 50-move counter was just reset
+        if game_state.halfmove_clock() == 0 {
+            if let Some(value) = self.probe_tablebases(board) {
+                return value;
+            }
+        }
+
         if depth == 0 {
             return self.eval(board, game_state);
         }
 
+        // nothing left to search in a drawn king and pawn ending
+        if kpk::probe_board(board) == Some(false) {
+            return self.draw_score().into();
+        }
+
         let mut best_eval = if is_maximizing {
             EvaluationValue::CheckMate(false)
         } else {
@@ -255,15 +425,18 @@ impl Engine {
         for m in moves.iter() {
             // make the move
             let next_board = board.make_move_new(*m);
-            game_state.set_lastmove(*m);
+            let mut child_state = game_state;
+            child_state.make_move(board, *m, &self.eval_params);
+            self.push_move(board, *m);
             let eval = self.search_alpha_beta(
                 depth - 1,
                 &next_board,
                 alpha,
                 beta,
                 !is_maximizing,
-                game_state,
+                child_state,
             );
+            self.pop_move();
 
             if is_maximizing {
                 best_eval = best_eval.max(eval);
@@ -283,27 +456,116 @@ impl Engine {
     }
 
     pub fn eval(&self, board: &Board, mut game_state: GameState) -> EvaluationValue {
-        let mut eval = Evaluation::new(&self.board, &mut game_state);
-        let moves = self.gen_legal_moves(board);
-        eval.eval_board(board, &self.board_history)
-            .saturating_sub(eval.eval_mobility(&moves))
-            .into()
+        let mut eval = Evaluation::with_params(&self.board, &mut game_state, &self.eval_params)
+            .with_draw_score(self.draw_score());
+        match &self.evaluator {
+            Evaluator::Classic => eval.eval_board(board, &self.board_history).into(),
+            Evaluator::Nnue(net) => {
+                if let Some(value) = eval.game_over_value(board, &self.board_history) {
+                    return value.into();
+                }
+                // the network is no good at converting known endings
+                if let Some(value) = endgame::evaluate(board, &self.eval_params) {
+                    return match self.board.side_to_move() {
+                        Color::White => value.into(),
+                        Color::Black => (-value).into(),
+                    };
+                }
+                let acc = self
+                    .accumulators
+                    .last()
+                    .expect("search keeps an accumulator for every position");
+                let value = net.evaluate(acc, board.side_to_move());
+                if board.side_to_move() == self.board.side_to_move() {
+                    value.into()
+                } else {
+                    (-value).into()
+                }
+            }
+        }
+    }
+
+    /// NNUE evaluation of the current position from white's point of view, if a network is used
+    pub fn nnue_eval(&self) -> Option<isize> {
+        match &self.evaluator {
+            Evaluator::Classic => None,
+            Evaluator::Nnue(net) => {
+                let acc = Accumulator::refresh(net, &self.board);
+                let value = net.evaluate(&acc, self.board.side_to_move());
+                match self.board.side_to_move() {
+                    Color::White => Some(value),
+                    Color::Black => Some(-value),
+                }
+            }
+        }
+    }
+
+    /// the tablebase result of `board` from the engine's point of view
+    fn probe_tablebases(&mut self, board: &Board) -> Option<EvaluationValue> {
+        let tablebases = self.tablebases.as_ref()?;
+        if !tablebases.can_probe(board) {
+            return None;
+        }
+        let value = tablebases.probe_wdl(board)?.value();
+        self.tb_hits += 1;
+        let value = if board.side_to_move() == self.board.side_to_move() {
+            value
+        } else {
+            -value
+        };
+        // cursed wins and blessed losses stay a point either side of the draw
+        if value.abs() <= 1 {
+            Some((self.draw_score() + value).into())
+        } else {
+            Some(value.into())
+        }
+    }
+
+    /// only the root moves that keep the best tablebase result, so the search cannot throw a
+    /// won ending away to the 50-move rule
+    fn tablebase_root_moves(&mut self) -> Option<Vec<ChessMove>> {
+        let tablebases = self.tablebases.as_ref()?;
+        if !tablebases.can_probe(&self.board) {
+            return None;
+        }
+        let moves = tablebases.root_moves(&self.board, self.game_state.halfmove_clock())?;
+        self.tb_hits += 1;
+        Some(moves)
+    }
+
+    /// the specialised endgame evaluator for the current position and its value from white's
+    /// point of view
+    pub fn endgame_eval(&self) -> Option<(String, isize)> {
+        let (key, _, _) = endgame::find(&self.board)?;
+        let value = endgame::evaluate(&self.board, &self.eval_params)?;
+        Some((key, value))
+    }
+
+    /// every evaluation term of the current position, for the `eval` command
+    pub fn eval_trace(&self) -> EvalTrace {
+        let mut game_state = self.game_state;
+        Evaluation::with_params(&self.board, &mut game_state, &self.eval_params).trace(&self.board)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::str::FromStr;
+    use std::{str::FromStr, sync::Arc, time::Instant};
+
+    use chess::ChessMove;
+    use rand::{rngs::StdRng, SeedableRng};
 
     use super::Engine;
-    use crate::{eval::Evaluation, MaterialSumExt, OpeningDatabase};
+    use crate::{
+        eval::Evaluation, game_state::GameState, BookPolicy, MaterialSumExt, OpeningDatabase,
+    };
 
     #[test]
     fn best_move_checkmate() {
         let mut engine =
             Engine::from_str("r1b1kb2/pppp1p1p/2n1p2n/8/3q2r1/8/PPPPKPP1/RNBQ1BNR b q - 0 11")
                 .expect("IDIOT");
-        let eval = engine.search(1);
+        let eval = engine.search(1, GameState::new());
         assert_eq!(engine.best_move.unwrap().to_string(), "d4e4");
     }
 
@@ -312,7 +574,7 @@ mod test {
         let mut engine =
             Engine::from_str("rn2k1nr/ppp2ppp/8/3pp3/8/P1P3qb/1PQPPP2/RNB1KB2 w Qkq - 0 8")
                 .unwrap();
-        let eval = engine.search(3);
+        let eval = engine.search(3, GameState::new());
         assert_eq!(engine.get_best_mov().unwrap().to_string().as_str(), "f2g3");
     }
 
@@ -320,14 +582,16 @@ mod test {
     fn eval_board_black() {
         let engine = Engine::from_str("8/8/1P2K3/8/2n5/1q6/8/5k2 b - - 0 1").unwrap();
 
-        let eval = Evaluation::new(&engine.board).eval_board(engine.board(), engine.history());
+        let eval = Evaluation::new(&engine.board, &mut GameState::new())
+            .eval_board(engine.board(), engine.history());
         assert!(eval > 0);
     }
 
     #[test]
     fn eval_board_white() {
         let engine = Engine::from_str("8/8/1P2K3/8/2n5/1q6/8/5k2 w - - 0 1").unwrap();
-        let eval = Evaluation::new(&engine.board).eval_board(engine.board(), engine.history());
+        let eval = Evaluation::new(&engine.board, &mut GameState::new())
+            .eval_board(engine.board(), engine.history());
         assert!(eval < 0);
     }
 
@@ -335,10 +599,88 @@ mod test {
     fn best_move_capture_free_pawn() {
         let mut engine =
             Engine::from_str("1nbqkbnr/1ppppppp/8/8/r1PP4/8/PP2PPPP/R1BQKBNR b KQk - 0 1").unwrap();
-        let eval = engine.search(1);
+        let eval = engine.search(1, GameState::new());
         assert_eq!(engine.get_best_mov().unwrap().to_string().as_str(), "a4c4");
     }
 
+    #[test]
+    fn book_moves_after_a_transposition() {
+        let mut db = OpeningDatabase::new();
+        db.add_png("1. d4 Nf6 2. c4 e6 3. Nc3 Bb4 *").unwrap();
+
+        // the same position through another move order
+        let mut engine = Engine::new();
+        engine.add_opening_db(Arc::new(db.clone()));
+        for mov in ["c2c4", "e7e6", "d2d4", "g8f6"] {
+            engine.play_move(ChessMove::from_str(mov).unwrap());
+        }
+        engine.search_iterative_deeping(Instant::now());
+        assert_eq!(engine.get_best_mov().unwrap().to_string(), "b1c3");
+
+        let mut engine =
+            Engine::from_str("rnbqkb1r/pppp1ppp/4pn2/8/2PP4/2N5/PP2PPPP/R1BQKBNR b KQkq - 1 3")
+                .unwrap();
+        engine.add_opening_db(Arc::new(db));
+        engine.search_iterative_deeping(Instant::now());
+        assert_eq!(engine.get_best_mov().unwrap().to_string(), "f8b4");
+    }
+
+    #[test]
+    fn book_line_follows_the_game() {
+        let mut db = OpeningDatabase::new();
+        db.add_png("1. e4 e5 2. Nf3 Nc6 (2... d6) *").unwrap();
+        let db = Arc::new(db);
+
+        let mut engine = Engine::new();
+        engine.add_opening_db(db.clone());
+        for mov in ["e2e4", "e7e5", "g1f3"] {
+            engine.play_move(ChessMove::from_str(mov).unwrap());
+        }
+        let replies = db.children(engine.book_cursor().unwrap());
+        assert_eq!(
+            replies
+                .iter()
+                .map(|(mov, _)| mov.to_string())
+                .collect::<Vec<_>>(),
+            ["b8c6", "d7d6"]
+        );
+        assert_eq!(engine.book_cursor().unwrap().path().len(), 3);
+        assert_eq!(engine.opening().unwrap().name, "King's Knight Opening");
+
+        // the same position set up from a FEN has no line, it is found by position only
+        let mut engine =
+            Engine::from_str("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2")
+                .unwrap();
+        engine.add_opening_db(db);
+        assert!(engine.book_cursor().is_none());
+        assert_eq!(engine.opening().unwrap().eco, "C40");
+        engine.search_iterative_deeping(Instant::now());
+        assert_eq!(engine.get_best_mov().unwrap().to_string(), "b8c6");
+    }
+
+    #[test]
+    fn seeded_book_moves_repeat() {
+        let mut db = OpeningDatabase::new();
+        for game in ["1. e4 e5 *", "1. d4 d5 *", "1. c4 e5 *", "1. Nf3 d5 *"] {
+            db.add_png(game).unwrap();
+        }
+        let openings = |seed| {
+            let mut engine = Engine::new();
+            engine.add_opening_db(Arc::new(db.clone()));
+            engine.set_book_policy(BookPolicy::Uniform);
+            engine.set_book_rng(StdRng::seed_from_u64(seed));
+            (0..10)
+                .map(|_| {
+                    engine.search_iterative_deeping(Instant::now());
+                    engine.get_best_mov().unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        let first = openings(45);
+        assert_eq!(first, openings(45));
+        assert!(first.iter().any(|mov| *mov != first[0]), "{first:?}");
+    }
+
     #[test]
     fn test_move_repetition() {
         let mut engine = Engine::new();
@@ -1,64 +1,113 @@
 use std::{cell::RefCell, rc::Rc, str::FromStr};
 
 use crate::{
-    game_state::GameState, BoardMaterial, MaterialSumExt, PieceFromColor, KING_MIDDLE_BLACK,
-    KING_MIDDLE_WHITE,
+    draw, endgame,
+    eval_params::EvalParams,
+    eval_state::TOTAL_PHASE,
+    eval_trace::{EvalTerm, EvalTrace, Score},
+    game_phase,
+    game_state::GameState,
+    see, PieceFromColor,
 };
-use chess::{BitBoard, Board, ChessMove, Color, MoveGen, Piece, Square};
-
-use crate::{
-    BISHOP_VALUE_PER_SQUARE_BLACK, BISHOP_VALUE_PER_SQUARE_WHITE, KNIGHT_VALUE_PER_SQUARE_BLACK,
-    KNIGHT_VALUE_PER_SQUARE_WHITE, PAWN_VALUE_PER_SQUARE_BLACK, PAWN_VALUE_PER_SQUARE_WHITE,
-    QUEEN_VALUE_PER_SQUARE_BLACK, QUEEN_VALUE_PER_SQUARE_WHITE, ROOK_VALUE_PER_SQUARE_BLACK,
-    ROOK_VALUE_PER_SQUARE_WHITE,
+use chess::{
+    between, get_bishop_moves, get_bishop_rays, get_king_moves, get_knight_moves, get_pawn_attacks,
+    get_rook_moves, get_rook_rays, BitBoard, Board, CastleRights, ChessMove, Color, File, MoveGen,
+    Piece, Rank, Square, EMPTY,
 };
 
-const KING_CASTEL_BONUS: isize = 50;
-const KING_CASTEL_PEN: isize = -30;
-const KING_CASTEL_RIGHT_BONUS: isize = 10;
-const KING_CASTEL_RIGHT_PEN: isize = -10;
+lazy_static::lazy_static! {
+    static ref DEFAULT_EVAL_PARAMS: EvalParams = EvalParams::default();
+}
+
+pub(crate) const DARK_SQUARES: BitBoard = BitBoard(0xAA55AA55AA55AA55);
+
+/// squares attacked by one side, split by the kind of attacker
+struct AttackMap {
+    pawns: BitBoard,
+    minors: BitBoard,
+    rooks: BitBoard,
+    all: BitBoard,
+}
+
+/// The king stands on its back rank towards a corner and no rook of its own is left between it
+/// and that corner, the way castling leaves it. A king that walked there keeps its rook trapped
+/// in the corner instead.
+fn looks_castled(board: &Board, color: Color) -> bool {
+    let king = board.king_square(color);
+    let back_rank = match color {
+        Color::White => Rank::First,
+        Color::Black => Rank::Eighth,
+    };
+    if king.get_rank() != back_rank {
+        return false;
+    }
+
+    let corner = match king.get_file() {
+        File::G | File::H => File::H,
+        File::A | File::B | File::C => File::A,
+        _ => return false,
+    };
+    let corner = Square::make_square(back_rank, corner);
+    let towards_corner = between(king, corner) | BitBoard::from_square(corner);
+    board.pieces_color(Piece::Rook, color) & towards_corner == EMPTY
+}
 
 pub struct Evaluation<'a> {
     engine_side: &'a Board,
     game_state: &'a mut GameState,
+    params: &'a EvalParams,
+    /// what any draw is worth to the engine side
+    draw_score: isize,
 }
 
 //TODO: make a game result enum for checkmate that has move count for checkmate
 
 impl<'a> Evaluation<'a> {
     pub fn new(engine_side: &'a Board, game_state: &'a mut GameState) -> Self {
+        Self::with_params(engine_side, game_state, &DEFAULT_EVAL_PARAMS)
+    }
+
+    pub fn with_params(
+        engine_side: &'a Board,
+        game_state: &'a mut GameState,
+        params: &'a EvalParams,
+    ) -> Self {
         Self {
             engine_side,
             game_state,
+            params,
+            draw_score: 0,
         }
     }
 
-    fn king_safety(&self, board: &Board) -> isize {
-        let king_bitboard = board.king_square(board.side_to_move());
-        // NOTE: king safety will be evaluated differently for endgame
-        if !self.game_state.game_phases().is_middle() {
-            return 0;
-        }
+    /// scores every draw as `draw_score` for the engine side instead of 0
+    pub fn with_draw_score(mut self, draw_score: isize) -> Self {
+        self.draw_score = draw_score;
+        self
+    }
+
+    /// NOTE: king safety is only a middle game term, the end game wants an active king instead
+    fn king_safety(&self, board: &Board, color: Color) -> isize {
+        let king_bitboard = board.king_square(color);
 
         let mut res = 0;
 
         let king_index = king_bitboard.to_index();
         assert!(king_index < 64);
 
-        let king_value = match board.side_to_move() {
-            Color::White => KING_MIDDLE_WHITE[king_bitboard.to_index()],
-            Color::Black => KING_MIDDLE_BLACK[king_bitboard.to_index()],
+        res += match color {
+            Color::White => self.params.king_middle_psqt[king_index],
+            Color::Black => self.params.king_middle_psqt[king_index ^ 56],
         };
-        res += king_value;
 
-        if self.game_state.has_castel(board.side_to_move()) {
-            res += KING_CASTEL_BONUS;
+        if self.game_state.has_castled(color) || looks_castled(board, color) {
+            res += self.params.king_castel_bonus;
         } else {
-            res += KING_CASTEL_PEN;
-            if self.game_state.has_castel_right(board.side_to_move()) {
-                res += KING_CASTEL_RIGHT_BONUS
+            res += self.params.king_castel_pen;
+            if board.castle_rights(color) != CastleRights::NoRights {
+                res += self.params.king_castel_right_bonus
             } else {
-                res += KING_CASTEL_RIGHT_PEN
+                res += self.params.king_castel_right_pen
             }
         };
 
@@ -122,11 +171,11 @@ impl<'a> Evaluation<'a> {
         };
 
         if (pawn_mask & board.combined()).0 == 0 {
-            let rank_bonus = match color {
-                Color::White => &[0, 10, 30, 40, 50, 60, 90, 0],
-                Color::Black => &[0, 90, 60, 50, 40, 30, 10, 0],
+            let relative_rank = match color {
+                Color::White => square.get_rank().to_index(),
+                Color::Black => 7 - square.get_rank().to_index(),
             };
-            15 + rank_bonus[square.get_rank().to_index()]
+            self.params.passed_pawn_base + self.params.passed_pawn_rank[relative_rank]
         } else {
             0
         }
@@ -137,7 +186,8 @@ impl<'a> Evaluation<'a> {
         BitBoard(72340172838076673 << file_idx)
     }
 
-    /// creates a bitmask to check if a pawn can be considered as passed pawned or not
+    /// creates a bitmask to check if a pawn can be considered as passed pawned or not, black
+    /// pawns move down the board so the mask covers the ranks below the pawn
     fn pass_pawn_bitmask_black(&self, square: Square) -> BitBoard {
         let a_file: u64 = 72340172838076673;
         let file_idx = square.get_file().to_index() as u64;
@@ -147,7 +197,7 @@ impl<'a> Evaluation<'a> {
         let right_file_idx: u64 = a_file << (file_idx + 1).min(7);
         let pawn_file = pawn_file | left_file_idx | right_file_idx;
 
-        let shift_rank = pawn_file << 8 * (square.get_rank().to_index() as u64 + 1);
+        let shift_rank = pawn_file >> (8 * (8 - square.get_rank().to_index() as u64));
         BitBoard(shift_rank)
     }
 
@@ -160,157 +210,261 @@ impl<'a> Evaluation<'a> {
         let right_file_idx: u64 = a_file << (file_idx + 1).min(7);
         let pawn_file = pawn_file | left_file_idx | right_file_idx;
 
-        let shift_rank = pawn_file >> 8 * (square.get_rank().to_index() as u64 + 1);
+        let shift_rank = pawn_file << (8 * (square.get_rank().to_index() as u64 + 1));
         BitBoard(shift_rank)
     }
 
-    /// adds a bonus for having a bishop pair of the engine side
-    fn favour_bishop_pair(&self, board: &Board) -> isize {
-        let b_p = board.pieces(Piece::Bishop);
-        if b_p.0 == 0 {
-            return 0;
+    /// adds a bonus for having a bishop pair
+    fn favour_bishop_pair(&self, board: &Board, color: Color) -> isize {
+        if board.pieces_color(Piece::Bishop, color).popcnt() >= 2 {
+            self.params.bishop_pair
+        } else {
+            0
         }
-        let color_bp = match board.side_to_move() {
-            Color::White => board.color_combined(Color::White) & b_p,
-            Color::Black => board.color_combined(Color::Black) & b_p,
-        };
+    }
 
-        if (board.side_to_move() == self.engine_side.side_to_move()) && color_bp.count() == 2 {
-            10
-        } else {
-            -10
+    fn material(&self, board: &Board, color: Color) -> isize {
+        match self.game_state.eval_state() {
+            Some(state) => {
+                debug_assert_eq!(state.material(color), self.material_scan(board, color));
+                state.material(color)
+            }
+            None => self.material_scan(board, color),
         }
     }
 
-    pub fn eval_board(&mut self, board: &Board, board_history: &[u64]) -> isize {
-        // if the position has been reached before at least 3 times it will be draw by three-fold
-        // repetition
-        let repeat_board = board_history
-            .iter()
-            .filter(|d| **d == board.get_hash())
-            .count();
+    fn material_scan(&self, board: &Board, color: Color) -> isize {
+        board
+            .color_combined(color)
+            .map(|square| match board.piece_on(square) {
+                Some(piece) => self.params.piece_value(piece),
+                None => 0,
+            })
+            .sum()
+    }
 
-        if repeat_board > 2 {
-            println!("REPEAT BOARD -> {repeat_board}");
-            return 0;
+    fn psqt(&self, board: &Board, color: Color) -> Score {
+        match self.game_state.eval_state() {
+            Some(state) => {
+                debug_assert_eq!(state.psqt(color), self.psqt_scan(board, color));
+                state.psqt(color)
+            }
+            None => self.psqt_scan(board, color),
         }
+    }
 
-        let mut value_based_on_pos: isize = 0;
-        for x in 0..64 {
-            let square = unsafe { Square::new(x) };
-            let piece = board.piece_on(square);
-            let color = board.color_on(square);
-
-            if let (Some(piece), Some(color)) = (piece, color) {
-                let piece_value = match piece {
-                    chess::Piece::Pawn => {
-                        let f = self.pass_pawn(board, square, color);
-                        value_based_on_pos += f;
-                        match color {
-                            Color::White => PAWN_VALUE_PER_SQUARE_WHITE[x as usize],
-                            Color::Black => PAWN_VALUE_PER_SQUARE_BLACK[x as usize],
-                        }
-                    }
-                    chess::Piece::Knight => match color {
-                        Color::White => KNIGHT_VALUE_PER_SQUARE_WHITE[x as usize],
-                        Color::Black => KNIGHT_VALUE_PER_SQUARE_BLACK[x as usize],
-                    },
-                    chess::Piece::Bishop => match color {
-                        Color::White => BISHOP_VALUE_PER_SQUARE_WHITE[x as usize],
-                        Color::Black => BISHOP_VALUE_PER_SQUARE_BLACK[x as usize],
-                    },
-                    // chess::Piece::King => match color {
-                    //     Color::White => KING_MIDDLE_WHITE[x as usize],
-                    //     Color::Black => KING_MIDDLE_BLACK[x as usize],
-                    // },
-                    chess::Piece::Rook => match color {
-                        Color::White => ROOK_VALUE_PER_SQUARE_WHITE[x as usize],
-                        Color::Black => ROOK_VALUE_PER_SQUARE_BLACK[x as usize],
-                    },
-                    chess::Piece::Queen => match color {
-                        Color::White => QUEEN_VALUE_PER_SQUARE_WHITE[x as usize],
-                        Color::Black => QUEEN_VALUE_PER_SQUARE_BLACK[x as usize],
-                    },
-                    _ => 0,
-                };
-
-                value_based_on_pos += piece_value;
+    fn psqt_scan(&self, board: &Board, color: Color) -> Score {
+        let res = board
+            .color_combined(color)
+            .map(|square| match board.piece_on(square) {
+                Some(piece) => self.params.psqt(piece, color, square),
+                None => 0,
+            })
+            .sum();
+        Score::both(res)
+    }
 
+    /// decrease the evals for pieces still on their original square - to encourage to move
+    /// pieces forward
+    fn development(&self, board: &Board, color: Color) -> isize {
+        let mut res = 0;
+        for square in *board.color_combined(color) {
+            if let Some(piece) = board.piece_on(square) {
                 if self.is_piece_on_original_pos(&piece, &square, &color) {
-                    // decrease the evals - to encourage to move pieces forward
-                    value_based_on_pos = value_based_on_pos.saturating_sub(5);
+                    res += self.params.development_pen;
                 }
             }
         }
+        res
+    }
 
-        let mat_val = match board.status() {
-            chess::BoardStatus::Ongoing => {
-                let board_sum = board.material_sum_bitboard();
-                let eval = match board.side_to_move() {
-                    Color::White => board_sum.white as isize - board_sum.black as isize,
-                    Color::Black => board_sum.black as isize - board_sum.white as isize,
-                };
+    fn passed_pawns(&self, board: &Board, color: Color) -> isize {
+        board
+            .pieces_color(Piece::Pawn, color)
+            .map(|square| self.pass_pawn(board, square, color))
+            .sum()
+    }
 
-                // game state is updated
-                self.game_state.update_game_phase(board_sum, board);
-
-                let mut eval = eval
-                    .saturating_add(self.discourage_queen_as_pinned(board))
-                    .saturating_sub(self.favour_bishop_pair(board))
-                    .saturating_sub(self.multiple_pawn_same_file(board))
-                    .saturating_add(value_based_on_pos);
-
-                if self.game_state.game_phases().is_end() {
-                    // check if opp king is in check -- give incentive
-                    if board.checkers() != &BitBoard(0)
-                        && self.engine_side.side_to_move() != board.side_to_move()
-                    {
-                        eval = eval.saturating_add(20);
-                    }
-
-                    eval = eval
-                        .saturating_add(self.rook_on_same_rank(board))
-                        .saturating_sub(self.push_enemy_king_to_edge(board));
-                }
+    /// the phase of `board`, from the incremental state when the search tracks one
+    fn phase(&self, board: &Board) -> i32 {
+        match self.game_state.eval_state() {
+            Some(state) => {
+                let phase = state.phase().min(TOTAL_PHASE);
+                debug_assert_eq!(phase, game_phase::phase(board));
+                phase
+            }
+            None => game_phase::phase(board),
+        }
+    }
 
-                if board.side_to_move() != self.engine_side.side_to_move() {
-                    -eval
-                } else {
-                    eval
-                }
+    /// Evaluates every term for both sides, the trace tapers them by the phase of `board`.
+    pub fn trace(&mut self, board: &Board) -> EvalTrace {
+        let mut trace = EvalTrace::new(self.phase(board));
+        let white_attacks = self.attack_map(board, Color::White);
+        let black_attacks = self.attack_map(board, Color::Black);
+
+        for color in [Color::White, Color::Black] {
+            let (attacks, enemy_attacks) = match color {
+                Color::White => (&white_attacks, &black_attacks),
+                Color::Black => (&black_attacks, &white_attacks),
+            };
+
+            let terms = [
+                (EvalTerm::Material, Score::both(self.material(board, color))),
+                (EvalTerm::Psqt, self.psqt(board, color)),
+                (
+                    EvalTerm::Development,
+                    Score::both(self.development(board, color)),
+                ),
+                (
+                    EvalTerm::PassedPawns,
+                    Score::both(self.passed_pawns(board, color)),
+                ),
+                (
+                    EvalTerm::DoubledPawns,
+                    Score::both(
+                        self.params.doubled_pawn_pen * self.multiple_pawn_same_file(board, color),
+                    ),
+                ),
+                (
+                    EvalTerm::BishopPair,
+                    Score::both(self.favour_bishop_pair(board, color)),
+                ),
+                (
+                    EvalTerm::PinnedQueen,
+                    Score::both(self.discourage_queen_as_pinned(board, color)),
+                ),
+                (
+                    EvalTerm::Threats,
+                    Score::both(self.threats_for(board, color, attacks, enemy_attacks)),
+                ),
+                (EvalTerm::Outposts, Score::both(self.outposts(board, color))),
+                (
+                    EvalTerm::BadBishop,
+                    Score::both(self.bad_bishop(board, color)),
+                ),
+                (EvalTerm::Mobility, Score::both(self.mobility(board, color))),
+                (
+                    EvalTerm::KingSafety,
+                    Score::mg(self.king_safety(board, color)),
+                ),
+                (
+                    EvalTerm::RookFile,
+                    Score::eg(self.rook_on_same_rank(board, color)),
+                ),
+                (
+                    EvalTerm::KingToEdge,
+                    Score::eg(self.push_enemy_king_to_edge(board, color)),
+                ),
+            ];
+            for (term, score) in terms {
+                trace.add(term, color, score);
             }
-            chess::BoardStatus::Stalemate => 0,
+        }
+
+        // check if opp king is in check -- give incentive
+        if *board.checkers() != EMPTY {
+            trace.add(
+                EvalTerm::Check,
+                !board.side_to_move(),
+                Score::eg(self.params.check_bonus),
+            );
+        }
+
+        // drawish endings only scale down the side that is ahead
+        let strong = if trace.total().eg >= 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        trace.set_scale(draw::scale_factor(board, strong));
+
+        trace
+    }
+
+    /// the value of a position where the game is over, `None` while it goes on
+    pub fn game_over_value(&self, board: &Board, board_history: &[u64]) -> Option<isize> {
+        // if the position has been reached before at least 3 times it will be draw by three-fold
+        // repetition
+        let repeat_board = board_history
+            .iter()
+            .filter(|d| **d == board.get_hash())
+            .count();
+
+        if repeat_board > 2 {
+            return Some(self.draw_score);
+        }
+
+        match board.status() {
+            chess::BoardStatus::Ongoing => (),
+            chess::BoardStatus::Stalemate => return Some(self.draw_score),
             chess::BoardStatus::Checkmate => {
                 if board.side_to_move() == self.engine_side.side_to_move() {
-                    -isize::MAX
+                    return Some(-isize::MAX);
                 } else {
-                    isize::MAX
+                    return Some(isize::MAX);
                 }
             }
-        };
+        }
 
-        mat_val
+        // a mate on the move that reaches the 100th ply still counts, so this comes after it
+        if self.game_state.halfmove_clock() >= 100 || draw::insufficient_material(board) {
+            return Some(self.draw_score);
+        }
+        None
     }
 
-    pub fn eval_mobility(&self, moves: &[ChessMove]) -> isize {
-        moves.len().saturating_mul(2).try_into().unwrap()
+    pub fn eval_board(&mut self, board: &Board, board_history: &[u64]) -> isize {
+        if let Some(value) = self.game_over_value(board, board_history) {
+            return value;
+        }
+
+        let eval = match endgame::evaluate(board, self.params) {
+            Some(value) => value,
+            None => self.trace(board).value(),
+        };
+        match self.engine_side.side_to_move() {
+            Color::White => eval,
+            Color::Black => -eval,
+        }
     }
 
-    fn multiple_pawn_same_file(&self, board: &Board) -> isize {
-        let pawn_bitboard = board.pieces_color(Piece::Pawn, board.side_to_move());
+    /// squares the pieces of `color` can move to, not counting pawns and the king
+    fn mobility(&self, board: &Board, color: Color) -> isize {
+        let blockers = *board.combined();
+        let own = *board.color_combined(color);
+        let mut res = 0;
+        for square in own {
+            let moves = match board.piece_on(square) {
+                Some(Piece::Knight) => get_knight_moves(square),
+                Some(Piece::Bishop) => get_bishop_moves(square, blockers),
+                Some(Piece::Rook) => get_rook_moves(square, blockers),
+                Some(Piece::Queen) => {
+                    get_bishop_moves(square, blockers) | get_rook_moves(square, blockers)
+                }
+                _ => EMPTY,
+            };
+            res += (moves & !own).popcnt() as isize;
+        }
+        res * self.params.mobility
+    }
+
+    /// returns the number of pawns stacked behind another pawn of the same colour
+    fn multiple_pawn_same_file(&self, board: &Board, color: Color) -> isize {
+        let pawn_bitboard = board.pieces_color(Piece::Pawn, color);
         let mut res: isize = 0;
         for x in 0..8 {
             let file_bitboard = self.file_bitboard(x);
             let pawns = file_bitboard & pawn_bitboard;
-            res *= pawns.0.count_ones() as isize;
+            res += (pawns.0.count_ones() as isize - 1).max(0);
         }
         res
     }
 
     /// --- END GAME SPECIFIC --- ///
 
-    fn rook_on_same_rank(&self, board: &Board) -> isize {
-        let rooks_bitboard = board.pieces_color(Piece::Rook, board.side_to_move());
+    fn rook_on_same_rank(&self, board: &Board, color: Color) -> isize {
+        let rooks_bitboard = board.pieces_color(Piece::Rook, color);
         let mut find = false;
 
         for x in 0..8 {
@@ -323,21 +477,192 @@ impl<'a> Evaluation<'a> {
         }
 
         if find {
-            15
+            self.params.rook_same_file_bonus
         } else {
-            -5
+            self.params.rook_same_file_pen
+        }
+    }
+
+    /// pieces of `color` pinned against their own king; unlike `Board::pinned` this also works
+    /// for the side that is not to move
+    fn pinned(&self, board: &Board, color: Color) -> BitBoard {
+        let king = board.king_square(color);
+        let enemy = board.color_combined(!color);
+        let diagonal = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+        let straight = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+        let snipers =
+            ((get_bishop_rays(king) & diagonal) | (get_rook_rays(king) & straight)) & enemy;
+
+        let mut pinned = EMPTY;
+        for sniper in snipers {
+            let blockers = between(king, sniper) & board.combined();
+            if blockers.popcnt() == 1 && (blockers & board.color_combined(color)) != EMPTY {
+                pinned |= blockers;
+            }
         }
+        pinned
     }
 
-    fn discourage_queen_as_pinned(&self, board: &Board) -> isize {
-        let queen_bitboard = board.pieces_color(Piece::Queen, board.side_to_move());
-        let pinned = board.pinned();
+    fn discourage_queen_as_pinned(&self, board: &Board, color: Color) -> isize {
+        let queen_bitboard = board.pieces_color(Piece::Queen, color);
+        let pinned = self.pinned(board, color);
 
         if (pinned & queen_bitboard).0 == 0 {
             0
         } else {
-            -20
+            self.params.pinned_queen_pen
+        }
+    }
+
+    fn attack_map(&self, board: &Board, color: Color) -> AttackMap {
+        let blockers = *board.combined();
+        let mut map = AttackMap {
+            pawns: EMPTY,
+            minors: EMPTY,
+            rooks: EMPTY,
+            all: EMPTY,
+        };
+
+        for sq in board.pieces_color(Piece::Pawn, color) {
+            map.pawns |= get_pawn_attacks(sq, color, !EMPTY);
+        }
+        for sq in board.pieces_color(Piece::Knight, color) {
+            map.minors |= get_knight_moves(sq);
+        }
+        for sq in board.pieces_color(Piece::Bishop, color) {
+            map.minors |= get_bishop_moves(sq, blockers);
+        }
+        for sq in board.pieces_color(Piece::Rook, color) {
+            map.rooks |= get_rook_moves(sq, blockers);
+        }
+
+        let mut queens = EMPTY;
+        for sq in board.pieces_color(Piece::Queen, color) {
+            queens |= get_rook_moves(sq, blockers) | get_bishop_moves(sq, blockers);
+        }
+        let king = get_king_moves(board.king_square(color));
+
+        map.all = map.pawns | map.minors | map.rooks | queens | king;
+        map
+    }
+
+    /// bonus for `color` attacking enemy pieces with something cheaper, attacking enemy pieces
+    /// that lose the exchange on their square, and having a safe pawn push that would attack a
+    /// piece
+    fn threats_for(
+        &self,
+        board: &Board,
+        color: Color,
+        attacks: &AttackMap,
+        enemy_attacks: &AttackMap,
+    ) -> isize {
+        let enemy = board.color_combined(!color);
+        let enemy_minors = (board.pieces(Piece::Knight) | board.pieces(Piece::Bishop)) & enemy;
+        let enemy_rooks = board.pieces(Piece::Rook) & enemy;
+        let enemy_queens = board.pieces(Piece::Queen) & enemy;
+        let enemy_pieces = enemy_minors | enemy_rooks | enemy_queens;
+
+        let mut res = 0;
+        res += self.params.threat_by_pawn * (attacks.pawns & enemy_pieces).popcnt() as isize;
+        res += self.params.threat_by_minor
+            * (attacks.minors & (enemy_rooks | enemy_queens)).popcnt() as isize;
+        res += self.params.threat_by_rook * (attacks.rooks & enemy_queens).popcnt() as isize;
+
+        // attacked pieces that cannot be held once the exchange on their square is played out
+        let enemy_non_king = enemy & !board.pieces(Piece::King);
+        let hanging = (enemy_non_king & attacks.all)
+            .filter(|sq| see::see_square(board, *sq, color) > 0)
+            .count();
+        res += self.params.hanging_piece * hanging as isize;
+
+        // a push is only a threat if the pawn is not simply taken on arrival
+        let mut push_threats = EMPTY;
+        for sq in board.pieces_color(Piece::Pawn, color) {
+            if let Some(push) = sq.forward(color) {
+                let push_bb = BitBoard::from_square(push);
+                if (push_bb & board.combined()) == EMPTY && (push_bb & enemy_attacks.pawns) == EMPTY
+                {
+                    push_threats |= get_pawn_attacks(push, color, enemy_pieces);
+                }
+            }
+        }
+        res += self.params.pawn_push_threat * push_threats.popcnt() as isize;
+
+        res
+    }
+
+    /// returns the squares in front of `square` (from the view of `color`) on the adjacent files,
+    /// i.e. where an enemy pawn would have to be to ever attack that square
+    fn pawn_attack_span(&self, square: Square, color: Color) -> BitBoard {
+        let file_idx = square.get_file().to_index();
+        let mut files = EMPTY;
+        if file_idx > 0 {
+            files |= self.file_bitboard(file_idx - 1);
         }
+        if file_idx < 7 {
+            files |= self.file_bitboard(file_idx + 1);
+        }
+
+        let rank_idx = square.get_rank().to_index();
+        let ahead = match color {
+            Color::White => BitBoard((!0u64).checked_shl(8 * (rank_idx as u32 + 1)).unwrap_or(0)),
+            Color::Black => BitBoard((1u64 << 8 * rank_idx as u64) - 1),
+        };
+        files & ahead
+    }
+
+    /// bonus for knights and bishops sitting on the 4th-6th rank, supported by a pawn and out of
+    /// reach of every enemy pawn
+    fn outposts(&self, board: &Board, color: Color) -> isize {
+        let own_pawns = board.pieces_color(Piece::Pawn, color);
+        let enemy_pawns = board.pieces_color(Piece::Pawn, !color);
+
+        let mut res = 0;
+        for (piece, bonus) in [
+            (Piece::Knight, self.params.knight_outpost),
+            (Piece::Bishop, self.params.bishop_outpost),
+        ] {
+            for sq in board.pieces_color(piece, color) {
+                let relative_rank = match color {
+                    Color::White => sq.get_rank().to_index(),
+                    Color::Black => 7 - sq.get_rank().to_index(),
+                };
+                if !(3..=5).contains(&relative_rank) {
+                    continue;
+                }
+                let supported = get_pawn_attacks(sq, !color, own_pawns) != EMPTY;
+                let attackable = (self.pawn_attack_span(sq, color) & enemy_pawns) != EMPTY;
+                if supported && !attackable {
+                    res += bonus;
+                }
+            }
+        }
+        res
+    }
+
+    /// penalty for bishops whose own pawns sit on the same square colour, more so when those
+    /// pawns can no longer move
+    fn bad_bishop(&self, board: &Board, color: Color) -> isize {
+        let own_pawns = board.pieces_color(Piece::Pawn, color);
+        let mut res = 0;
+        for sq in board.pieces_color(Piece::Bishop, color) {
+            let same_colour = if (BitBoard::from_square(sq) & DARK_SQUARES) != EMPTY {
+                DARK_SQUARES
+            } else {
+                !DARK_SQUARES
+            };
+            for pawn in own_pawns & same_colour {
+                res += self.params.bad_bishop_pawn_pen;
+                let blocked = pawn
+                    .forward(color)
+                    .map(|front| board.piece_on(front).is_some())
+                    .unwrap_or(false);
+                if blocked {
+                    res += self.params.bad_bishop_blocked_pawn_pen;
+                }
+            }
+        }
+        res
     }
 
     fn rook_prefer_open_file(&self, board: &Board) -> isize {
@@ -345,21 +670,158 @@ impl<'a> Evaluation<'a> {
     }
 
     /// return a positive value if king is in edge of the board or returns a negative value if not
-    fn push_enemy_king_to_edge(&self, board: &Board) -> isize {
-        let edge_bitboard = BitBoard(0xff818181818181ff);
-        let enemy_color = match board.side_to_move() {
-            Color::White => Color::Black,
-            Color::Black => Color::White,
-        };
+    fn push_enemy_king_to_edge(&self, board: &Board, color: Color) -> isize {
+        let enemy_color = !color;
         let enemy_king = board.pieces_color(Piece::King, enemy_color);
         assert_eq!(enemy_king.0.count_ones(), 1);
         let enemy_king_square = enemy_king.to_square();
-        KING_EDGE[enemy_king_square.to_index()]
+        self.params.king_edge[enemy_king_square.to_index()]
     }
 }
 
 pub const KING_EDGE: &[isize; 64] = &[
     16, 16, 16, 16, 16, 16, 16, 16, 16, 14, 14, 14, 14, 14, 14, 16, 16, 14, -10, -10, -10, -10, 14,
-    16, 16, 14, -10, -20, -20, -10, 14, 16, 16, 14, -10, -20, -20, -20, 14, 16, 16, 14, -10, -10,
-    -10, -10, 14, 16, 16, 14, 14, 14, 14, 14, 14, 16, 16, 14, 14, 14, 14, 14, 14, 16,
+    16, 16, 14, -10, -20, -20, -10, 14, 16, 16, 14, -10, -20, -20, -10, 14, 16, 16, 14, -10, -10,
+    -10, -10, 14, 16, 16, 14, 14, 14, 14, 14, 14, 16, 16, 16, 16, 16, 16, 16, 16, 16,
 ];
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chess::{BitBoard, Board, Color, Square, EMPTY};
+
+    use super::{looks_castled, Evaluation};
+    use crate::{eval_params::EvalParams, eval_trace::EvalTerm, game_state::GameState};
+
+    #[test]
+    fn knight_outpost_supported_by_pawn() {
+        let board = Board::from_str("4k3/pp3ppp/8/3N4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        let mut game_state = GameState::new();
+        let eval = Evaluation::new(&board, &mut game_state);
+        assert!(eval.outposts(&board, Color::White) > 0);
+
+        // a black pawn on c7 can still chase the knight away
+        let board = Board::from_str("4k3/2p2ppp/8/3N4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(eval.outposts(&board, Color::White), 0);
+    }
+
+    #[test]
+    fn passed_pawn_masks_look_ahead() {
+        let mut game_state = GameState::new();
+        let board = Board::default();
+        let eval = Evaluation::new(&board, &mut game_state);
+
+        let e4 = Square::from_str("e4").unwrap();
+        let ahead = BitBoard::from_square(Square::from_str("d7").unwrap());
+        let behind = BitBoard::from_square(Square::from_str("f2").unwrap());
+        assert_ne!(eval.pass_pawn_bitmask_white(e4) & ahead, EMPTY);
+        assert_eq!(eval.pass_pawn_bitmask_white(e4) & behind, EMPTY);
+        assert_eq!(eval.pass_pawn_bitmask_black(e4) & ahead, EMPTY);
+        assert_ne!(eval.pass_pawn_bitmask_black(e4) & behind, EMPTY);
+
+        // nothing in front of the e5 pawn, the black h-pawn is not passed
+        let board = Board::from_str("k7/7p/8/4P3/8/8/6P1/4K3 w - - 0 1").unwrap();
+        assert!(eval.passed_pawns(&board, Color::White) > 0);
+        assert_eq!(eval.passed_pawns(&board, Color::Black), 0);
+    }
+
+    #[test]
+    fn castled_king_is_rewarded() {
+        let castled = Board::from_str("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R4RK1 b kq - 1 1").unwrap();
+        let walked = Board::from_str("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R5KR b kq - 1 1").unwrap();
+        let mut game_state = GameState::new();
+        let eval = Evaluation::new(&castled, &mut game_state);
+        assert!(looks_castled(&castled, Color::White));
+        assert!(!looks_castled(&walked, Color::White));
+        assert!(!looks_castled(&castled, Color::Black));
+
+        // the king is on g1 either way, white gave up castling by walking there
+        let params = EvalParams::default();
+        assert_eq!(
+            eval.king_safety(&castled, Color::White) - eval.king_safety(&walked, Color::White),
+            params.king_castel_bonus - params.king_castel_pen - params.king_castel_right_pen
+        );
+        // black has not castled yet but still may
+        assert_eq!(
+            eval.king_safety(&castled, Color::Black),
+            params.king_middle_psqt[4] + params.king_castel_pen + params.king_castel_right_bonus
+        );
+    }
+
+    #[test]
+    fn hanging_piece_is_a_threat() {
+        // the black knight on e5 is attacked by the rook and defended by nothing
+        let board = Board::from_str("4k3/8/8/4n3/8/8/8/4RK2 w - - 0 1").unwrap();
+        let mut game_state = GameState::new();
+        let trace = Evaluation::new(&board, &mut game_state).trace(&board);
+        assert!(trace.get(EvalTerm::Threats, Color::White).mg > 0);
+        assert_eq!(trace.get(EvalTerm::Threats, Color::Black).mg, 0);
+    }
+
+    #[test]
+    fn bad_bishop_behind_own_pawns() {
+        let board = Board::from_str("4k3/8/8/3p4/3P4/4P3/3B4/4K3 w - - 0 1").unwrap();
+        let mut game_state = GameState::new();
+        let eval = Evaluation::new(&board, &mut game_state);
+        assert!(eval.bad_bishop(&board, Color::White) < 0);
+    }
+
+    #[test]
+    fn trace_adds_up_to_eval_board() {
+        let board =
+            Board::from_str("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                .unwrap();
+        let mut game_state = GameState::new();
+        let mut eval = Evaluation::new(&board, &mut game_state);
+        let trace = eval.trace(&board);
+        assert_eq!(trace.value(), eval.eval_board(&board, &[]));
+    }
+
+    #[test]
+    fn dead_draws_evaluate_to_zero() {
+        let board = Board::from_str("4k3/8/8/8/8/8/8/3NK3 w - - 0 1").unwrap();
+        let mut game_state = GameState::new();
+        assert_eq!(
+            Evaluation::new(&board, &mut game_state).eval_board(&board, &[]),
+            0
+        );
+
+        let board = Board::from_str("4k3/8/8/8/8/8/8/Q3K3 w - - 0 1").unwrap();
+        let mut game_state = GameState::new();
+        game_state.set_halfmove_clock(100);
+        assert_eq!(
+            Evaluation::new(&board, &mut game_state).eval_board(&board, &[]),
+            0
+        );
+    }
+
+    #[test]
+    fn every_draw_uses_the_draw_score() {
+        let draw_value = |fen: &str, halfmove_clock: u32, history: &[u64]| {
+            let board = Board::from_str(fen).unwrap();
+            let mut game_state = GameState::new();
+            game_state.set_halfmove_clock(halfmove_clock);
+            Evaluation::new(&board, &mut game_state)
+                .with_draw_score(-25)
+                .game_over_value(&board, history)
+        };
+
+        let start = Board::default();
+        let repeated = [start.get_hash(); 3];
+        assert_eq!(draw_value(&start.to_string(), 0, &repeated), Some(-25));
+        assert_eq!(
+            draw_value("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 0, &[]),
+            Some(-25)
+        );
+        assert_eq!(
+            draw_value("4k3/8/8/8/8/8/8/Q3K3 w - - 0 1", 100, &[]),
+            Some(-25)
+        );
+        assert_eq!(
+            draw_value("4k3/8/8/8/8/8/8/3BK3 w - - 0 1", 0, &[]),
+            Some(-25)
+        );
+        assert_eq!(draw_value("4k3/8/8/8/8/8/8/Q3K3 w - - 0 1", 0, &[]), None);
+    }
+}
@@ -0,0 +1,450 @@
+use std::{fmt::Display, fs, io, path::Path, str::FromStr};
+
+use crate::{
+    eval::KING_EDGE, BISHOP_VALUE_PER_SQUARE_WHITE, KING_MIDDLE_WHITE,
+    KNIGHT_VALUE_PER_SQUARE_WHITE, PAWN_VALUE_PER_SQUARE_WHITE, PIECE_VALUE_MAP,
+    QUEEN_VALUE_PER_SQUARE_WHITE, ROOK_VALUE_PER_SQUARE_WHITE,
+};
+
+/// Every weight used by `Evaluation`.
+///
+/// Square tables are written from white's point of view; black looks them up with the ranks
+/// flipped. The file format is a weight name followed by its values, `#` starts a comment
+/// and weights missing from a file keep their default value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalParams {
+    pub pawn_value: isize,
+    pub knight_value: isize,
+    pub bishop_value: isize,
+    pub rook_value: isize,
+    pub queen_value: isize,
+
+    pub pawn_psqt: [isize; 64],
+    pub knight_psqt: [isize; 64],
+    pub bishop_psqt: [isize; 64],
+    pub rook_psqt: [isize; 64],
+    pub queen_psqt: [isize; 64],
+    pub king_middle_psqt: [isize; 64],
+
+    pub development_pen: isize,
+    pub passed_pawn_base: isize,
+    /// indexed by the rank of the pawn as seen from its own side
+    pub passed_pawn_rank: [isize; 8],
+    pub doubled_pawn_pen: isize,
+    pub bishop_pair: isize,
+    pub pinned_queen_pen: isize,
+
+    pub threat_by_pawn: isize,
+    pub threat_by_minor: isize,
+    pub threat_by_rook: isize,
+    pub hanging_piece: isize,
+    pub pawn_push_threat: isize,
+    pub knight_outpost: isize,
+    pub bishop_outpost: isize,
+    pub bad_bishop_pawn_pen: isize,
+    pub bad_bishop_blocked_pawn_pen: isize,
+
+    pub mobility: isize,
+
+    pub king_castel_bonus: isize,
+    pub king_castel_pen: isize,
+    pub king_castel_right_bonus: isize,
+    pub king_castel_right_pen: isize,
+
+    pub rook_same_file_bonus: isize,
+    pub rook_same_file_pen: isize,
+    pub king_edge: [isize; 64],
+    pub check_bonus: isize,
+}
+
+fn table(values: &[isize]) -> [isize; 64] {
+    values.try_into().expect("square tables have 64 entries")
+}
+
+fn piece_value(piece: chess::Piece) -> isize {
+    *PIECE_VALUE_MAP
+        .get(&piece)
+        .expect("every piece has a value") as isize
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self {
+            pawn_value: piece_value(chess::Piece::Pawn),
+            knight_value: piece_value(chess::Piece::Knight),
+            bishop_value: piece_value(chess::Piece::Bishop),
+            rook_value: piece_value(chess::Piece::Rook),
+            queen_value: piece_value(chess::Piece::Queen),
+
+            pawn_psqt: table(&PAWN_VALUE_PER_SQUARE_WHITE),
+            knight_psqt: table(&KNIGHT_VALUE_PER_SQUARE_WHITE),
+            bishop_psqt: table(&BISHOP_VALUE_PER_SQUARE_WHITE),
+            rook_psqt: table(&ROOK_VALUE_PER_SQUARE_WHITE),
+            queen_psqt: table(&QUEEN_VALUE_PER_SQUARE_WHITE),
+            king_middle_psqt: KING_MIDDLE_WHITE,
+
+            development_pen: -5,
+            passed_pawn_base: 15,
+            passed_pawn_rank: [0, 10, 30, 40, 50, 60, 90, 0],
+            doubled_pawn_pen: -1,
+            bishop_pair: 10,
+            pinned_queen_pen: -20,
+
+            threat_by_pawn: 40,
+            threat_by_minor: 25,
+            threat_by_rook: 20,
+            hanging_piece: 30,
+            pawn_push_threat: 15,
+            knight_outpost: 25,
+            bishop_outpost: 12,
+            bad_bishop_pawn_pen: -3,
+            bad_bishop_blocked_pawn_pen: -5,
+
+            mobility: 2,
+
+            king_castel_bonus: 50,
+            king_castel_pen: -30,
+            king_castel_right_bonus: 10,
+            king_castel_right_pen: -10,
+
+            rook_same_file_bonus: 15,
+            rook_same_file_pen: -5,
+            king_edge: *KING_EDGE,
+            check_bonus: 20,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EvalParamsError {
+    Io(io::Error),
+    UnknownWeight {
+        line: usize,
+        name: String,
+    },
+    InvalidNumber {
+        line: usize,
+        value: String,
+    },
+    WrongLength {
+        line: usize,
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl Display for EvalParamsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalParamsError::Io(err) => write!(f, "could not read eval file: {err}"),
+            EvalParamsError::UnknownWeight { line, name } => {
+                write!(f, "line {line}: unknown weight `{name}`")
+            }
+            EvalParamsError::InvalidNumber { line, value } => {
+                write!(f, "line {line}: `{value}` is not a number")
+            }
+            EvalParamsError::WrongLength {
+                line,
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {line}: `{name}` expects {expected} values but {found} were given"
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for EvalParamsError {
+    fn from(value: io::Error) -> Self {
+        EvalParamsError::Io(value)
+    }
+}
+
+/// a weight is a single value or a table of them
+trait Weight {
+    fn values(&self) -> &[isize];
+    fn values_mut(&mut self) -> &mut [isize];
+}
+
+impl Weight for isize {
+    fn values(&self) -> &[isize] {
+        std::slice::from_ref(self)
+    }
+
+    fn values_mut(&mut self) -> &mut [isize] {
+        std::slice::from_mut(self)
+    }
+}
+
+impl<const N: usize> Weight for [isize; N] {
+    fn values(&self) -> &[isize] {
+        self
+    }
+
+    fn values_mut(&mut self) -> &mut [isize] {
+        self
+    }
+}
+
+/// `weights` and `weights_mut` from one list of fields, so both always have the same order
+macro_rules! weights {
+    ($($field:ident),* $(,)?) => {
+        impl EvalParams {
+            /// every weight by name, in the order they are written to a file
+            pub fn weights(&self) -> Vec<(&'static str, &[isize])> {
+                vec![$((stringify!($field), self.$field.values())),*]
+            }
+
+            /// same as `weights` but mutable, used when loading a file
+            pub fn weights_mut(&mut self) -> Vec<(&'static str, &mut [isize])> {
+                vec![$((stringify!($field), self.$field.values_mut())),*]
+            }
+        }
+    };
+}
+
+weights!(
+    pawn_value,
+    knight_value,
+    bishop_value,
+    rook_value,
+    queen_value,
+    pawn_psqt,
+    knight_psqt,
+    bishop_psqt,
+    rook_psqt,
+    queen_psqt,
+    king_middle_psqt,
+    development_pen,
+    passed_pawn_base,
+    passed_pawn_rank,
+    doubled_pawn_pen,
+    bishop_pair,
+    pinned_queen_pen,
+    threat_by_pawn,
+    threat_by_minor,
+    threat_by_rook,
+    hanging_piece,
+    pawn_push_threat,
+    knight_outpost,
+    bishop_outpost,
+    bad_bishop_pawn_pen,
+    bad_bishop_blocked_pawn_pen,
+    mobility,
+    king_castel_bonus,
+    king_castel_pen,
+    king_castel_right_bonus,
+    king_castel_right_pen,
+    rook_same_file_bonus,
+    rook_same_file_pen,
+    king_edge,
+    check_bonus,
+);
+
+impl EvalParams {
+    /// material value of a piece, the king is not counted
+    pub fn piece_value(&self, piece: chess::Piece) -> isize {
+        match piece {
+            chess::Piece::Pawn => self.pawn_value,
+            chess::Piece::Knight => self.knight_value,
+            chess::Piece::Bishop => self.bishop_value,
+            chess::Piece::Rook => self.rook_value,
+            chess::Piece::Queen => self.queen_value,
+            chess::Piece::King => 0,
+        }
+    }
+
+    /// square table value of a piece, black looks the white table up with the ranks flipped.
+    /// The king is scored by king safety instead.
+    pub fn psqt(&self, piece: chess::Piece, color: chess::Color, square: chess::Square) -> isize {
+        let table = match piece {
+            chess::Piece::Pawn => &self.pawn_psqt,
+            chess::Piece::Knight => &self.knight_psqt,
+            chess::Piece::Bishop => &self.bishop_psqt,
+            chess::Piece::Rook => &self.rook_psqt,
+            chess::Piece::Queen => &self.queen_psqt,
+            chess::Piece::King => return 0,
+        };
+        match color {
+            chess::Color::White => table[square.to_index()],
+            chess::Color::Black => table[square.to_index() ^ 56],
+        }
+    }
+
+    /// every weight flattened into one list, in the order of `weights`
+    pub fn to_vec(&self) -> Vec<isize> {
+        self.weights()
+            .into_iter()
+            .flat_map(|(_, values)| values.iter().copied())
+            .collect()
+    }
+
+    /// the reverse of `to_vec`
+    pub fn set_from_slice(&mut self, values: &[isize]) {
+        let mut values = values.iter();
+        for (_, weight) in self.weights_mut() {
+            for w in weight.iter_mut() {
+                *w = *values.next().expect("one value for every weight");
+            }
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, EvalParamsError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), EvalParamsError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+/// a weight name with the values read after it so far
+struct PendingWeight<'s> {
+    line: usize,
+    name: &'s str,
+    values: Vec<isize>,
+}
+
+impl FromStr for EvalParams {
+    type Err = EvalParamsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut params = EvalParams::default();
+        let mut weights = params.weights_mut();
+
+        let mut store = |pending: PendingWeight| -> Result<(), EvalParamsError> {
+            let weight = weights
+                .iter_mut()
+                .find(|(n, _)| *n == pending.name)
+                .map(|(_, w)| w)
+                .ok_or_else(|| EvalParamsError::UnknownWeight {
+                    line: pending.line,
+                    name: pending.name.to_string(),
+                })?;
+
+            if weight.len() != pending.values.len() {
+                return Err(EvalParamsError::WrongLength {
+                    line: pending.line,
+                    name: pending.name.to_string(),
+                    expected: weight.len(),
+                    found: pending.values.len(),
+                });
+            }
+            weight.copy_from_slice(&pending.values);
+            Ok(())
+        };
+
+        // values may span several lines (square tables), so a weight ends where the next name
+        // starts
+        let mut pending: Option<PendingWeight> = None;
+        for (idx, line) in s.lines().enumerate() {
+            let line_no = idx + 1;
+            let line = line.split('#').next().unwrap_or_default();
+            for token in line.split_whitespace() {
+                if token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                    if let Some(done) = pending.take() {
+                        store(done)?;
+                    }
+                    pending = Some(PendingWeight {
+                        line: line_no,
+                        name: token,
+                        values: Vec::new(),
+                    });
+                    continue;
+                }
+
+                let value = token
+                    .parse::<isize>()
+                    .map_err(|_| EvalParamsError::InvalidNumber {
+                        line: line_no,
+                        value: token.to_string(),
+                    })?;
+                match pending.as_mut() {
+                    Some(weight) => weight.values.push(value),
+                    None => {
+                        return Err(EvalParamsError::InvalidNumber {
+                            line: line_no,
+                            value: token.to_string(),
+                        })
+                    }
+                }
+            }
+        }
+        if let Some(done) = pending.take() {
+            store(done)?;
+        }
+
+        drop(weights);
+        Ok(params)
+    }
+}
+
+impl Display for EvalParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, values) in self.weights() {
+            if values.len() == 64 {
+                // square tables are written rank by rank to keep them readable
+                writeln!(f, "{name}")?;
+                for rank in values.chunks(8) {
+                    let rank = rank.iter().map(|v| format!("{v:>4}")).collect::<String>();
+                    writeln!(f, "   {rank}")?;
+                }
+            } else {
+                let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+                writeln!(f, "{name} {}", values.join(" "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EvalParams, EvalParamsError};
+
+    #[test]
+    fn round_trip() {
+        let mut params = EvalParams::default();
+        params.knight_value = 310;
+        params.pawn_psqt[12] = -7;
+        let parsed: EvalParams = params.to_string().parse().unwrap();
+        assert_eq!(parsed, params);
+    }
+
+    #[test]
+    fn flatten_round_trip() {
+        let mut params = EvalParams::default();
+        let mut values = params.to_vec();
+        values[0] = 95;
+        params.set_from_slice(&values);
+        assert_eq!(params.pawn_value, 95);
+        assert_eq!(params.to_vec(), values);
+    }
+
+    #[test]
+    fn missing_weights_keep_default() {
+        let parsed: EvalParams = "# only the bishop\nbishop_value 345\n".parse().unwrap();
+        assert_eq!(parsed.bishop_value, 345);
+        assert_eq!(parsed.rook_value, EvalParams::default().rook_value);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(matches!(
+            "queen_value 900 1".parse::<EvalParams>(),
+            Err(EvalParamsError::WrongLength { line: 1, .. })
+        ));
+        assert!(matches!(
+            "kings_value 1".parse::<EvalParams>(),
+            Err(EvalParamsError::UnknownWeight { line: 1, .. })
+        ));
+        assert!(matches!(
+            "pawn_value 1o0".parse::<EvalParams>(),
+            Err(EvalParamsError::InvalidNumber { line: 1, .. })
+        ));
+    }
+}
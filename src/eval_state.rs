@@ -0,0 +1,152 @@
+use chess::{Board, ChessMove, Color, Piece, Square, ALL_PIECES};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{eval_params::EvalParams, eval_trace::Score, MoveChangesExt, PieceChange};
+
+/// phase weight of every piece, a full board adds up to `TOTAL_PHASE`
+pub const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const TOTAL_PHASE: i32 = 24;
+
+lazy_static::lazy_static! {
+    /// random keys for a pawn of each colour on each square
+    static ref PAWN_ZOBRIST: [[u64; 64]; 2] = {
+        let mut rng = StdRng::seed_from_u64(0x5041_574e);
+        let mut keys = [[0; 64]; 2];
+        for color in keys.iter_mut() {
+            for key in color.iter_mut() {
+                *key = rng.gen();
+            }
+        }
+        keys
+    };
+}
+
+/// The parts of the evaluation that only depend on which piece stands where, kept up to date
+/// move by move during the search instead of scanning the board at every leaf.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvalState {
+    material: [isize; 2],
+    psqt: [Score; 2],
+    phase: i32,
+    pawn_key: u64,
+}
+
+impl EvalState {
+    pub fn from_board(board: &Board, params: &EvalParams) -> Self {
+        let mut state = Self::default();
+        for piece in ALL_PIECES {
+            for color in [Color::White, Color::Black] {
+                let bb = board.pieces(piece) & board.color_combined(color);
+                for square in bb {
+                    state.add(params, piece, color, square);
+                }
+            }
+        }
+        state
+    }
+
+    fn add(&mut self, params: &EvalParams, piece: Piece, color: Color, square: Square) {
+        let side = color.to_index();
+        self.material[side] += params.piece_value(piece);
+        self.psqt[side] = self.psqt[side] + Score::both(params.psqt(piece, color, square));
+        self.phase += PHASE_WEIGHTS[piece.to_index()];
+        if piece == Piece::Pawn {
+            self.pawn_key ^= PAWN_ZOBRIST[side][square.to_index()];
+        }
+    }
+
+    fn remove(&mut self, params: &EvalParams, piece: Piece, color: Color, square: Square) {
+        let side = color.to_index();
+        self.material[side] -= params.piece_value(piece);
+        self.psqt[side] = self.psqt[side] - Score::both(params.psqt(piece, color, square));
+        self.phase -= PHASE_WEIGHTS[piece.to_index()];
+        if piece == Piece::Pawn {
+            self.pawn_key ^= PAWN_ZOBRIST[side][square.to_index()];
+        }
+    }
+
+    /// updates the state for `mov` played on `board`
+    pub fn make_move(&mut self, params: &EvalParams, board: &Board, mov: ChessMove) {
+        for change in board.move_changes(mov).into_iter().flatten() {
+            match change {
+                PieceChange::Add(piece, color, square) => self.add(params, piece, color, square),
+                PieceChange::Remove(piece, color, square) => {
+                    self.remove(params, piece, color, square)
+                }
+            }
+        }
+    }
+
+    pub fn material(&self, color: Color) -> isize {
+        self.material[color.to_index()]
+    }
+
+    pub fn psqt(&self, color: Color) -> Score {
+        self.psqt[color.to_index()]
+    }
+
+    /// sum of `PHASE_WEIGHTS` of the pieces on the board; promotions can push it past
+    /// `TOTAL_PHASE`
+    pub fn phase(&self) -> i32 {
+        self.phase
+    }
+
+    /// Zobrist key of the pawn structure alone
+    pub fn pawn_key(&self) -> u64 {
+        self.pawn_key
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chess::{Board, ChessMove};
+
+    use super::*;
+
+    #[test]
+    fn incremental_matches_from_scratch() {
+        let params = EvalParams::default();
+        let lines = [
+            (
+                "r3k2r/pppq1ppp/2np1n2/2b1p3/2B1P1b1/2NP1N2/PPPQ1PPP/R3K2R w KQkq - 0 1",
+                vec!["e1g1", "e8c8", "c3d5", "f6d5", "e4d5"],
+            ),
+            (
+                "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+                vec!["e5d6", "e8f7", "d6d7", "f7e7", "d7d8q"],
+            ),
+        ];
+
+        for (fen, moves) in lines {
+            let mut board = Board::from_str(fen).unwrap();
+            let mut state = EvalState::from_board(&board, &params);
+            for mov in moves {
+                let mov = ChessMove::from_str(mov).unwrap();
+                state.make_move(&params, &board, mov);
+                board = board.make_move_new(mov);
+                assert_eq!(state, EvalState::from_board(&board, &params));
+            }
+        }
+    }
+
+    #[test]
+    fn start_position_totals() {
+        let state = EvalState::from_board(&Board::default(), &EvalParams::default());
+        assert_eq!(state.phase(), TOTAL_PHASE);
+        assert_eq!(state.material(Color::White), state.material(Color::Black));
+        assert_eq!(state.psqt(Color::White), state.psqt(Color::Black));
+    }
+
+    #[test]
+    fn pawn_key_ignores_pieces() {
+        let params = EvalParams::default();
+        let a = Board::from_str("4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 1").unwrap();
+        let b = Board::from_str("3qk3/pp6/8/8/8/8/PP6/2N1K3 w - - 0 1").unwrap();
+        assert_eq!(
+            EvalState::from_board(&a, &params).pawn_key(),
+            EvalState::from_board(&b, &params).pawn_key()
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Colour symmetry of the evaluation: a position and the same position with the colours swapped
+//! and the board flipped must get the same score for the side to move.
+
+use std::str::FromStr;
+
+use chess::{Board, MoveGen};
+use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+
+use crate::{consts::FEN_STRING, eval::Evaluation, game_state::GameState};
+
+/// the FEN with white and black swapped and the ranks reversed
+fn flip_fen(fen: &str) -> String {
+    let fields = fen.split_whitespace().collect::<Vec<_>>();
+    let swap_case = |s: &str| {
+        s.chars()
+            .map(|c| {
+                if c.is_ascii_uppercase() {
+                    c.to_ascii_lowercase()
+                } else {
+                    c.to_ascii_uppercase()
+                }
+            })
+            .collect::<String>()
+    };
+
+    let placement = fields[0].split('/').rev().collect::<Vec<_>>().join("/");
+    let side = if fields[1] == "w" { "b" } else { "w" };
+    let castling = match fields.get(2) {
+        Some(&"-") | None => String::from("-"),
+        Some(castling) => {
+            let swapped = swap_case(castling);
+            let mut order = swapped.chars().collect::<Vec<_>>();
+            order.sort_by_key(|c| "KQkq".find(*c));
+            order.into_iter().collect()
+        }
+    };
+    let en_passant = match fields.get(3) {
+        Some(&"-") | None => String::from("-"),
+        Some(sq) => sq.replace('3', "x").replace('6', "3").replace('x', "6"),
+    };
+    let clocks = fields.get(4..).map(|c| c.join(" ")).unwrap_or_default();
+
+    format!(
+        "{} {side} {castling} {en_passant} {clocks}",
+        swap_case(&placement)
+    )
+    .trim_end()
+    .to_string()
+}
+
+fn test_positions() -> Vec<String> {
+    include_str!("../test_position.json")
+        .split('"')
+        .skip(1)
+        .step_by(2)
+        .map(String::from)
+        .collect()
+}
+
+/// the score of the side to move and the score of white
+fn scores(board: &Board) -> (isize, isize) {
+    let mut game_state = GameState::new();
+    let mut eval = Evaluation::new(board, &mut game_state);
+    let to_move = eval.eval_board(board, &[]);
+    let white = eval.trace(board).value();
+    (to_move, white)
+}
+
+fn assert_symmetric(board: &Board) {
+    let flipped = Board::from_str(&flip_fen(&board.to_string())).unwrap();
+    let (to_move, white) = scores(board);
+    let (flipped_to_move, flipped_white) = scores(&flipped);
+    assert_eq!(to_move, flipped_to_move, "{board} and {flipped}");
+    assert_eq!(white, -flipped_white, "{board} and {flipped}");
+}
+
+#[test]
+fn flip_fen_round_trips() {
+    let fen = "r3k2r/p1pp1pb1/bn2Qnp1/2qPN3/1p2P3/2N5/PPPBBPPP/R3K1R1 b Qkq - 3 2";
+    assert_eq!(
+        flip_fen(fen),
+        "r3k1r1/pppbbppp/2n5/1P2p3/2Qpn3/BN2qNP1/P1PP1PB1/R3K2R w KQq - 3 2"
+    );
+    assert_eq!(flip_fen(&flip_fen(fen)), fen);
+    assert_eq!(
+        flip_fen("8/8/8/2k5/2pP4/8/B7/4K3 b - d3 0 3"),
+        "4k3/b7/8/2Pp4/2K5/8/8/8 w - d6 0 3"
+    );
+}
+
+#[test]
+fn known_positions_are_symmetric() {
+    for fen in FEN_STRING.iter().cloned().chain(test_positions()) {
+        let board = Board::from_str(&fen).expect(&fen);
+        assert_symmetric(&board);
+    }
+}
+
+#[test]
+fn random_positions_are_symmetric() {
+    let mut rng = StdRng::seed_from_u64(0x5359_4d4d);
+    for _ in 0..40 {
+        let mut board = Board::default();
+        for _ in 0..60 {
+            let Some(mov) = MoveGen::new_legal(&board).choose(&mut rng) else {
+                break;
+            };
+            board = board.make_move_new(mov);
+            assert_symmetric(&board);
+        }
+    }
+}
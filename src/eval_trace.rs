@@ -0,0 +1,253 @@
+use std::fmt::{self, Display};
+
+use chess::Color;
+
+use crate::{draw::SCALE_FACTOR_NORMAL, eval_state::TOTAL_PHASE, game_phase::GamePhases};
+
+/// every term the `Evaluation` adds up, in the order they are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EvalTerm {
+    Material,
+    Psqt,
+    Development,
+    PassedPawns,
+    DoubledPawns,
+    BishopPair,
+    PinnedQueen,
+    Threats,
+    Outposts,
+    BadBishop,
+    Mobility,
+    KingSafety,
+    RookFile,
+    KingToEdge,
+    Check,
+}
+
+impl EvalTerm {
+    pub const ALL: [EvalTerm; 15] = [
+        EvalTerm::Material,
+        EvalTerm::Psqt,
+        EvalTerm::Development,
+        EvalTerm::PassedPawns,
+        EvalTerm::DoubledPawns,
+        EvalTerm::BishopPair,
+        EvalTerm::PinnedQueen,
+        EvalTerm::Threats,
+        EvalTerm::Outposts,
+        EvalTerm::BadBishop,
+        EvalTerm::Mobility,
+        EvalTerm::KingSafety,
+        EvalTerm::RookFile,
+        EvalTerm::KingToEdge,
+        EvalTerm::Check,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EvalTerm::Material => "Material",
+            EvalTerm::Psqt => "PSQT",
+            EvalTerm::Development => "Development",
+            EvalTerm::PassedPawns => "Passed pawns",
+            EvalTerm::DoubledPawns => "Doubled pawns",
+            EvalTerm::BishopPair => "Bishop pair",
+            EvalTerm::PinnedQueen => "Pinned queen",
+            EvalTerm::Threats => "Threats",
+            EvalTerm::Outposts => "Outposts",
+            EvalTerm::BadBishop => "Bad bishop",
+            EvalTerm::Mobility => "Mobility",
+            EvalTerm::KingSafety => "King safety",
+            EvalTerm::RookFile => "Rook file",
+            EvalTerm::KingToEdge => "King to edge",
+            EvalTerm::Check => "Check",
+        }
+    }
+
+    fn index(&self) -> usize {
+        EvalTerm::ALL
+            .iter()
+            .position(|t| t == self)
+            .expect("every term is listed in EvalTerm::ALL")
+    }
+}
+
+/// a value split into its middle game and end game part
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+    pub mg: isize,
+    pub eg: isize,
+}
+
+impl Score {
+    pub fn new(mg: isize, eg: isize) -> Self {
+        Self { mg, eg }
+    }
+
+    /// a term that counts the same in every phase
+    pub fn both(value: isize) -> Self {
+        Self::new(value, value)
+    }
+
+    pub fn mg(value: isize) -> Self {
+        Self::new(value, 0)
+    }
+
+    pub fn eg(value: isize) -> Self {
+        Self::new(0, value)
+    }
+
+    /// blends the two parts, all middle game at `TOTAL_PHASE` and all end game at 0
+    pub fn value(&self, phase: i32) -> isize {
+        let phase = phase.clamp(0, TOTAL_PHASE) as isize;
+        let total = TOTAL_PHASE as isize;
+        (self.mg * phase + self.eg * (total - phase)) / total
+    }
+}
+
+impl std::ops::Add for Score {
+    type Output = Score;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Score::new(
+            self.mg.saturating_add(rhs.mg),
+            self.eg.saturating_add(rhs.eg),
+        )
+    }
+}
+
+impl std::ops::Sub for Score {
+    type Output = Score;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Score::new(
+            self.mg.saturating_sub(rhs.mg),
+            self.eg.saturating_sub(rhs.eg),
+        )
+    }
+}
+
+/// Every evaluation term for both sides. Values are bonuses for the side they belong to, so the
+/// total from white's point of view is `white - black`.
+#[derive(Debug, Clone)]
+pub struct EvalTrace {
+    terms: [[Score; 2]; EvalTerm::ALL.len()],
+    /// 0 to `TOTAL_PHASE`, see `game_phase::phase`
+    phase: i32,
+    /// how much of the end game total counts, out of `SCALE_FACTOR_NORMAL`
+    scale: isize,
+}
+
+impl EvalTrace {
+    pub fn new(phase: i32) -> Self {
+        Self {
+            terms: [[Score::default(); 2]; EvalTerm::ALL.len()],
+            phase,
+            scale: SCALE_FACTOR_NORMAL,
+        }
+    }
+
+    pub fn add(&mut self, term: EvalTerm, color: Color, score: Score) {
+        let entry = &mut self.terms[term.index()][color.to_index()];
+        *entry = *entry + score;
+    }
+
+    pub fn get(&self, term: EvalTerm, color: Color) -> Score {
+        self.terms[term.index()][color.to_index()]
+    }
+
+    /// `white - black` for a single term
+    pub fn term_total(&self, term: EvalTerm) -> Score {
+        self.get(term, Color::White) - self.get(term, Color::Black)
+    }
+
+    /// sum of every term for one side
+    pub fn side_total(&self, color: Color) -> Score {
+        EvalTerm::ALL
+            .iter()
+            .fold(Score::default(), |acc, term| acc + self.get(*term, color))
+    }
+
+    /// sum of every term from white's point of view
+    pub fn total(&self) -> Score {
+        self.side_total(Color::White) - self.side_total(Color::Black)
+    }
+
+    pub fn phase(&self) -> i32 {
+        self.phase
+    }
+
+    pub fn scale(&self) -> isize {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: isize) {
+        self.scale = scale;
+    }
+
+    /// the final evaluation from white's point of view, tapered between the middle and end game
+    /// totals by the phase, the end game part scaled down for drawish endings
+    pub fn value(&self) -> isize {
+        let total = self.total();
+        let scaled = Score::new(total.mg, total.eg * self.scale / SCALE_FACTOR_NORMAL);
+        scaled.value(self.phase)
+    }
+}
+
+fn pawns(value: isize) -> String {
+    format!("{:>6.2}", value as f64 / 100.0)
+}
+
+fn score_cells(score: Score) -> String {
+    format!("{} {}", pawns(score.mg), pawns(score.eg))
+}
+
+impl Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sep = "----------------+---------------+---------------+---------------";
+        writeln!(
+            f,
+            "{:>15} |{:^15}|{:^15}|{:^15}",
+            "Term", "White", "Black", "Total"
+        )?;
+        writeln!(
+            f,
+            "{:>15} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+            "", "MG", "EG", "MG", "EG", "MG", "EG"
+        )?;
+        writeln!(f, "{sep}")?;
+        for term in EvalTerm::ALL.iter() {
+            writeln!(
+                f,
+                "{:>15} | {} | {} | {}",
+                term.name(),
+                score_cells(self.get(*term, Color::White)),
+                score_cells(self.get(*term, Color::Black)),
+                score_cells(self.term_total(*term)),
+            )?;
+        }
+        writeln!(f, "{sep}")?;
+        writeln!(
+            f,
+            "{:>15} | {} | {} | {}",
+            "Total",
+            score_cells(self.side_total(Color::White)),
+            score_cells(self.side_total(Color::Black)),
+            score_cells(self.total()),
+        )?;
+        writeln!(f)?;
+        let stage = match GamePhases::from_phase(self.phase) {
+            GamePhases::Opening => "opening",
+            GamePhases::MiddleGame => "middle game",
+            GamePhases::EndGame => "end game",
+        };
+        writeln!(f, "Phase: {}/{TOTAL_PHASE} ({stage})", self.phase)?;
+        if self.scale != SCALE_FACTOR_NORMAL {
+            writeln!(f, "Scale factor: {}/{SCALE_FACTOR_NORMAL}", self.scale)?;
+        }
+        write!(
+            f,
+            "Final evaluation: {} (white side)",
+            pawns(self.value()).trim()
+        )
+    }
+}
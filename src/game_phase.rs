@@ -1,63 +1,51 @@
-use chess::{Board, Piece};
+use chess::{Board, ALL_PIECES};
 
-use crate::{BoardMaterial, PieceFromColor};
+use crate::eval_state::{PHASE_WEIGHTS, TOTAL_PHASE};
 
-#[derive(Clone, Copy, PartialEq)]
+/// phase at or above which hardly anything has been traded yet
+const OPENING_PHASE: i32 = 22;
+/// phase at or below which the position counts as an ending
+const ENDGAME_PHASE: i32 = 8;
+
+/// Material left on the board, from 0 with only kings and pawns to `TOTAL_PHASE` with every
+/// piece. Promotions cannot push it past `TOTAL_PHASE`.
+pub fn phase(board: &Board) -> i32 {
+    ALL_PIECES
+        .iter()
+        .map(|piece| board.pieces(*piece).popcnt() as i32 * PHASE_WEIGHTS[piece.to_index()])
+        .sum::<i32>()
+        .min(TOTAL_PHASE)
+}
+
+/// the coarse stage of the game, for the code that only needs to know which side of a
+/// threshold the phase is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum GamePhases {
+    #[default]
     Opening,
     MiddleGame,
     EndGame,
 }
 
-impl Default for GamePhases {
-    fn default() -> Self {
-        Self::Opening
-    }
-}
-
 impl GamePhases {
-    pub fn update(&mut self, mat: BoardMaterial, board: &Board) {
-        // can only set the end if it's in middle or opening;
-        if self.is_end() {
-            return;
-        }
-        if board.pieces(Piece::Queen).0 == 0 {
-            self.set_endgame();
+    pub fn from_phase(phase: i32) -> Self {
+        if phase >= OPENING_PHASE {
+            GamePhases::Opening
+        } else if phase <= ENDGAME_PHASE {
+            GamePhases::EndGame
         } else {
-            let mut total = 0;
-            let pawn_bitboard = board.pieces(Piece::Pawn);
-            let pawn_count = pawn_bitboard.0.count_ones();
-            if pawn_count <= 12 {
-                total += 1;
-            }
-
-            let knight_bitboar = board.pieces(Piece::Knight);
-            let knight_count = knight_bitboar.0.count_ones();
-            if knight_count <= 3 {
-                total += 1;
-            }
-
-            let bishop_bitboard = board.pieces(Piece::Bishop);
-            let bishop_count = bishop_bitboard.0.count_ones();
-            if bishop_count <= 3 {
-                total += 1;
-            }
-
-            let rook_bitboard = board.pieces(Piece::Rook);
-            let rook_count = rook_bitboard.0.count_ones();
-            if rook_count <= 3 {
-                total += 1;
-            }
-
-            if total >= 2 {
-                self.set_endgame();
-            }
+            GamePhases::MiddleGame
         }
     }
 
-    pub fn set_endgame(&mut self) {
-        *self = GamePhases::EndGame
+    pub fn from_board(board: &Board) -> Self {
+        Self::from_phase(phase(board))
     }
+
+    pub fn is_opening(&self) -> bool {
+        *self == GamePhases::Opening
+    }
+
     pub fn is_middle(&self) -> bool {
         *self == GamePhases::MiddleGame
     }
@@ -65,8 +53,42 @@ impl GamePhases {
     pub fn is_end(&self) -> bool {
         *self == GamePhases::EndGame
     }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chess::Board;
+
+    use super::*;
+    use crate::eval_trace::Score;
+
+    #[test]
+    fn phase_follows_material() {
+        let start = Board::default();
+        assert_eq!(phase(&start), TOTAL_PHASE);
+        assert_eq!(GamePhases::from_board(&start), GamePhases::Opening);
+
+        // trading the queens alone no longer makes it an ending
+        let no_queens =
+            Board::from_str("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1").unwrap();
+        assert_eq!(phase(&no_queens), 16);
+        assert_eq!(GamePhases::from_board(&no_queens), GamePhases::MiddleGame);
+
+        let rooks = Board::from_str("3rk3/pppp4/8/8/8/8/PPPP4/3RK3 w - - 0 1").unwrap();
+        assert_eq!(GamePhases::from_board(&rooks), GamePhases::EndGame);
+
+        // a board full of promoted queens counts no more than the start position
+        let queens = Board::from_str("qqqqkqqq/8/8/8/8/8/8/QQQQKQQQ w - - 0 1").unwrap();
+        assert_eq!(phase(&queens), TOTAL_PHASE);
+    }
 
-    pub fn set_middlegame(&mut self) {
-        *self = GamePhases::EndGame
+    #[test]
+    fn scores_taper_between_phases() {
+        let score = Score::new(100, 20);
+        assert_eq!(score.value(TOTAL_PHASE), 100);
+        assert_eq!(score.value(TOTAL_PHASE / 2), 60);
+        assert_eq!(score.value(0), 20);
     }
 }
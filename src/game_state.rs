@@ -1,21 +1,22 @@
-use chess::{Board, ChessMove};
+use chess::{Board, ChessMove, Color, Piece};
 
-use crate::{game_phase::GamePhases, BoardMaterial};
+use crate::{eval_params::EvalParams, eval_state::EvalState};
 
 #[derive(Clone, Copy, Default)]
 pub struct GameState {
-    game_phases: GamePhases,
     last_move: Option<ChessMove>,
-    has_black_castel: bool,
-    has_white_castel: bool,
-    black_castel_right: bool,
-    white_castel_right: bool,
+    /// whether each side has castled in the moves played since this state was created
+    castled: [bool; 2],
+    /// plies since the last capture or pawn move, for the 50-move rule
+    halfmove_clock: u32,
+    /// `None` until the search starts tracking it, the evaluation then falls back to scanning
+    /// the board
+    eval_state: Option<EvalState>,
 }
 
 impl GameState {
     pub fn new() -> Self {
         Self {
-            game_phases: GamePhases::Opening,
             last_move: None,
             ..Default::default()
         }
@@ -25,51 +26,83 @@ impl GameState {
         if let Some(mov) = self.last_move {}
     }
 
-    pub fn has_castel(&self, color: chess::Color) -> bool {
-        match color {
-            chess::Color::White => self.has_white_castel,
-            chess::Color::Black => self.has_black_castel,
-        }
+    /// Only knows about castling seen through `make_move`, a game started from a FEN has to look
+    /// at the board as well.
+    pub fn has_castled(&self, color: Color) -> bool {
+        self.castled[color.to_index()]
     }
 
-    pub fn has_opp_castel(&self, color: chess::Color) -> bool {
-        match color {
-            chess::Color::Black => self.has_white_castel,
-            chess::Color::White => self.has_black_castel,
-        }
+    pub fn last_move(&self) -> &Option<ChessMove> {
+        &self.last_move
     }
 
-    pub fn has_castel_right(&self, color: chess::Color) -> bool {
-        match color {
-            chess::Color::White => self.white_castel_right,
-            chess::Color::Black => self.black_castel_right,
-        }
+    pub fn set_lastmove(&mut self, mov: ChessMove) {
+        self.last_move = Some(mov)
     }
 
-    pub fn has_opp_castel_right(&self, color: chess::Color) -> bool {
-        match color {
-            chess::Color::Black => self.white_castel_right,
-            chess::Color::White => self.black_castel_right,
-        }
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
     }
 
-    pub fn game_phases(&self) -> &GamePhases {
-        &self.game_phases
+    pub fn set_halfmove_clock(&mut self, halfmove_clock: u32) {
+        self.halfmove_clock = halfmove_clock;
     }
 
-    pub fn last_move(&self) -> &Option<ChessMove> {
-        &self.last_move
+    pub fn eval_state(&self) -> Option<&EvalState> {
+        self.eval_state.as_ref()
     }
 
-    pub fn set_gamephases_middlegame(&mut self) {
-        self.game_phases.set_middlegame()
+    /// starts tracking the incremental evaluation state from `board`
+    pub fn refresh_eval_state(&mut self, board: &Board, params: &EvalParams) {
+        self.eval_state = Some(EvalState::from_board(board, params));
     }
 
-    pub fn set_lastmove(&mut self, mov: ChessMove) {
-        self.last_move = Some(mov)
+    /// records `mov` played on `board` as the last move, advances the halfmove clock and updates
+    /// the incremental evaluation state if it is tracked
+    pub fn make_move(&mut self, board: &Board, mov: ChessMove, params: &EvalParams) {
+        self.set_lastmove(mov);
+        if let Some(color) = board.color_on(mov.get_source()) {
+            let king_move = board.piece_on(mov.get_source()) == Some(Piece::King);
+            let files = mov.get_source().get_file().to_index() as isize
+                - mov.get_dest().get_file().to_index() as isize;
+            if king_move && files.abs() == 2 {
+                self.castled[color.to_index()] = true;
+            }
+        }
+        let is_capture = board.piece_on(mov.get_dest()).is_some();
+        if is_capture || board.piece_on(mov.get_source()) == Some(Piece::Pawn) {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if let Some(state) = self.eval_state.as_mut() {
+            state.make_move(params, board, mov);
+        }
     }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chess::{Board, ChessMove, Color};
 
-    pub fn update_game_phase(&mut self, board_materail: BoardMaterial, board: &Board) {
-        self.game_phases.update(board_materail, board);
+    use super::GameState;
+    use crate::eval_params::EvalParams;
+
+    #[test]
+    fn castling_is_tracked_through_moves() {
+        let params = EvalParams::default();
+        let mut board =
+            Board::from_str("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1").unwrap();
+        let mut game_state = GameState::new();
+        for mov in ["e1g1", "e8d8", "a1b1", "d8e8"] {
+            let mov = ChessMove::from_str(mov).unwrap();
+            game_state.make_move(&board, mov, &params);
+            board = board.make_move_new(mov);
+        }
+        assert!(game_state.has_castled(Color::White));
+        // walking the king is not castling
+        assert!(!game_state.has_castled(Color::Black));
     }
 }
@@ -0,0 +1,263 @@
+//! King and pawn against king bitbase.
+//!
+//! Every position with the pawn on files a to d is classified by retrograde analysis the first
+//! time the bitbase is probed, the other files are mirrored. Only the won positions are kept, one
+//! bit each, so the whole table is 24 KiB.
+
+use chess::{get_king_moves, get_pawn_attacks, BitBoard, Board, Color, File, Piece, Rank, Square};
+
+use crate::draw::square_distance;
+
+/// 2 sides to move * 24 pawn squares * 64 * 64 king squares
+const MAX_INDEX: usize = 2 * 24 * 64 * 64;
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+lazy_static::lazy_static! {
+    static ref KPK_BITBASE: Vec<u64> = generate();
+}
+
+/// `stm` is 0 for white, the strong side, and 1 for black
+fn index(stm: usize, black_king: Square, white_king: Square, pawn: Square) -> usize {
+    white_king.to_index()
+        | (black_king.to_index() << 6)
+        | (stm << 12)
+        | (pawn.get_file().to_index() << 13)
+        | ((6 - pawn.get_rank().to_index()) << 15)
+}
+
+fn square(index: usize) -> Square {
+    Square::make_square(Rank::from_index(index / 8), File::from_index(index % 8))
+}
+
+fn pawn_attacks(pawn: Square) -> BitBoard {
+    get_pawn_attacks(pawn, Color::White, !chess::EMPTY)
+}
+
+struct Position {
+    stm: usize,
+    white_king: Square,
+    black_king: Square,
+    pawn: Square,
+    result: u8,
+}
+
+impl Position {
+    fn new(idx: usize) -> Self {
+        let white_king = square(idx & 0x3f);
+        let black_king = square((idx >> 6) & 0x3f);
+        let stm = (idx >> 12) & 1;
+        let pawn = Square::make_square(
+            Rank::from_index(6 - ((idx >> 15) & 7)),
+            File::from_index((idx >> 13) & 3),
+        );
+        let mut position = Self {
+            stm,
+            white_king,
+            black_king,
+            pawn,
+            result: UNKNOWN,
+        };
+        position.result = position.initial_result();
+        position
+    }
+
+    fn initial_result(&self) -> u8 {
+        let black_king = BitBoard::from_square(self.black_king);
+        if square_distance(self.white_king, self.black_king) <= 1
+            || self.white_king == self.pawn
+            || self.black_king == self.pawn
+            || (self.stm == 0 && pawn_attacks(self.pawn) & black_king != chess::EMPTY)
+        {
+            return INVALID;
+        }
+
+        // the pawn promotes and the new queen cannot be taken
+        if self.stm == 0 && self.pawn.get_rank() == Rank::Seventh {
+            let queen = self.pawn.up().expect("the pawn is on the seventh rank");
+            if self.white_king != queen
+                && self.black_king != queen
+                && (square_distance(self.black_king, queen) > 1
+                    || square_distance(self.white_king, queen) == 1)
+            {
+                return WIN;
+            }
+        }
+
+        // stalemate, or the black king takes an undefended pawn
+        if self.stm == 1 {
+            let escapes = get_king_moves(self.black_king)
+                & !(get_king_moves(self.white_king) | pawn_attacks(self.pawn));
+            let takes_pawn = get_king_moves(self.black_king)
+                & !get_king_moves(self.white_king)
+                & BitBoard::from_square(self.pawn);
+            if escapes == chess::EMPTY || takes_pawn != chess::EMPTY {
+                return DRAW;
+            }
+        }
+
+        UNKNOWN
+    }
+
+    /// looks at every move from here; white needs one move to a win, black one move to a draw
+    fn classify(&self, db: &[Position]) -> u8 {
+        let (good, bad) = if self.stm == 0 {
+            (WIN, DRAW)
+        } else {
+            (DRAW, WIN)
+        };
+
+        let mut r = INVALID;
+        if self.stm == 0 {
+            for to in get_king_moves(self.white_king) {
+                r |= db[index(1, self.black_king, to, self.pawn)].result;
+            }
+
+            if self.pawn.get_rank() < Rank::Seventh {
+                let push = self.pawn.up().expect("the pawn is below the seventh rank");
+                r |= db[index(1, self.black_king, self.white_king, push)].result;
+
+                if self.pawn.get_rank() == Rank::Second
+                    && push != self.white_king
+                    && push != self.black_king
+                {
+                    let double = push.up().expect("the pawn is on the second rank");
+                    r |= db[index(1, self.black_king, self.white_king, double)].result;
+                }
+            }
+        } else {
+            for to in get_king_moves(self.black_king) {
+                r |= db[index(0, to, self.white_king, self.pawn)].result;
+            }
+        }
+
+        if r & good != 0 {
+            good
+        } else if r & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            bad
+        }
+    }
+}
+
+fn generate() -> Vec<u64> {
+    let mut db = (0..MAX_INDEX).map(Position::new).collect::<Vec<_>>();
+
+    // keep going until every position that can be decided is
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in 0..MAX_INDEX {
+            if db[idx].result == UNKNOWN {
+                let result = db[idx].classify(&db);
+                if result != UNKNOWN {
+                    db[idx].result = result;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut bits = vec![0u64; MAX_INDEX / 64];
+    for (idx, position) in db.iter().enumerate() {
+        if position.result == WIN {
+            bits[idx / 64] |= 1 << (idx % 64);
+        }
+    }
+    bits
+}
+
+/// Whether the side with the pawn wins. Squares are given as they stand on the board, the
+/// position is mirrored so that the strong side is white with its pawn on files a to d.
+pub fn probe(
+    strong: Color,
+    strong_king: Square,
+    pawn: Square,
+    weak_king: Square,
+    side_to_move: Color,
+) -> bool {
+    let normalise = |sq: Square| {
+        let rank = match strong {
+            Color::White => sq.get_rank(),
+            Color::Black => Rank::from_index(7 - sq.get_rank().to_index()),
+        };
+        let file = if pawn.get_file() > File::D {
+            File::from_index(7 - sq.get_file().to_index())
+        } else {
+            sq.get_file()
+        };
+        Square::make_square(rank, file)
+    };
+
+    let stm = (side_to_move != strong) as usize;
+    let idx = index(
+        stm,
+        normalise(weak_king),
+        normalise(strong_king),
+        normalise(pawn),
+    );
+    KPK_BITBASE[idx / 64] & (1 << (idx % 64)) != 0
+}
+
+/// `Some(strong side wins)` when the board is a king and pawn against king position
+pub fn probe_board(board: &Board) -> Option<bool> {
+    let pawns = board.pieces(Piece::Pawn);
+    if board.combined().popcnt() != 3 || pawns.popcnt() != 1 {
+        return None;
+    }
+    let pawn = pawns.to_square();
+    let strong = board.color_on(pawn)?;
+    Some(probe(
+        strong,
+        board.king_square(strong),
+        pawn,
+        board.king_square(!strong),
+        board.side_to_move(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chess::Board;
+
+    use super::*;
+
+    fn wins(fen: &str) -> bool {
+        probe_board(&Board::from_str(fen).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn known_positions() {
+        // king on the sixth in front of its pawn wins whoever moves
+        assert!(wins("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"));
+        assert!(wins("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"));
+        // same for black, and on the other wing
+        assert!(wins("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1"));
+        assert!(wins("6k1/8/6K1/6P1/8/8/8/8 w - - 0 1"));
+
+        // the pawn on the seventh wins with white to move and is stalemate with black to move
+        assert!(wins("4k3/4P3/4K3/8/8/8/8/8 w - - 0 1"));
+        assert!(!wins("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"));
+        // the defending king keeps the opposition in front of the pawn
+        assert!(!wins("4k3/8/4P3/4K3/8/8/8/8 w - - 0 1"));
+        // a rook pawn cannot be forced through a king in the corner
+        assert!(!wins("k7/8/8/8/8/8/P7/K7 w - - 0 1"));
+        assert!(!wins("7k/8/8/8/8/8/7P/7K w - - 0 1"));
+    }
+
+    #[test]
+    fn runaway_pawn() {
+        // the black king is outside the square of the pawn
+        assert!(wins("7k/8/8/8/P7/8/8/4K3 w - - 0 1"));
+        // one step away from the square, so it depends on who moves
+        assert!(wins("8/8/8/5k2/P7/8/8/7K w - - 0 1"));
+        assert!(!wins("8/8/8/5k2/P7/8/8/7K b - - 0 1"));
+        assert!(probe_board(&Board::from_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap()).is_none());
+    }
+}
@@ -1,18 +1,52 @@
 #![feature(test)]
 #![allow(warnings)]
-use chess::{BitBoard, Board, Color, Piece, Square};
+use chess::{BitBoard, Board, ChessMove, Color, File, Piece, Square};
 pub(crate) mod consts;
+pub(crate) mod draw;
+pub(crate) mod eco;
+pub(crate) mod endgame;
 pub(crate) mod engine;
 pub(crate) mod eval;
+pub(crate) mod eval_params;
+pub(crate) mod eval_state;
+#[cfg(test)]
+mod eval_symmetry;
+pub(crate) mod eval_trace;
 pub(crate) mod evaluation_value;
 pub(crate) mod game_phase;
 pub(crate) mod game_state;
+pub(crate) mod kpk;
+pub(crate) mod nnue;
 pub(crate) mod opening;
+pub(crate) mod pgn;
+pub(crate) mod polyglot;
+pub(crate) mod see;
+pub(crate) mod syzygy;
+#[cfg(test)]
+mod test_util;
 pub(crate) mod trie;
+pub mod tuner;
 pub mod uci;
 
 pub use consts::*;
-pub use opening::OpeningDatabase;
+pub use eco::{EcoOpening, EcoTable};
+pub use engine::Evaluator;
+pub use eval::Evaluation;
+pub use eval_params::{EvalParams, EvalParamsError};
+pub use eval_state::EvalState;
+pub use eval_trace::{EvalTerm, EvalTrace, Score};
+pub use game_phase::GamePhases;
+pub use game_state::GameState;
+pub use nnue::{Accumulator, Network, NnueError};
+pub use opening::{
+    BookCursor, BookError, BookOptions, BookPolicy, MoveStats, OpeningDatabase, BAD_EXIT_EVAL,
+    BOOK_VERSION, DEFAULT_BOOK_PLIES,
+};
+pub use pgn::{parse_games, GameResult, PgnError, PgnGame, PgnMove, PgnReader};
+pub use polyglot::{polyglot_key, PolyglotBook, PolyglotEntry, PolyglotError};
+pub use see::{see, see_ge, see_square};
+pub use syzygy::{Tablebases, Wdl};
+pub use trie::{Lines, Node, Trie, TrieCodec, TrieCursor, TrieError, TrieStats};
 pub use uci::*;
 
 pub trait PieceFromColor {
@@ -36,6 +70,67 @@ impl PieceFromColor for Board {
     }
 }
 
+/// a piece appearing on or disappearing from a square
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PieceChange {
+    Add(Piece, Color, Square),
+    Remove(Piece, Color, Square),
+}
+
+pub trait MoveChangesExt {
+    /// every piece placement that changes when `mov` is played, used to update evaluation state
+    /// incrementally instead of looking at the whole board again
+    fn move_changes(&self, mov: ChessMove) -> [Option<PieceChange>; 4];
+}
+
+impl MoveChangesExt for Board {
+    fn move_changes(&self, mov: ChessMove) -> [Option<PieceChange>; 4] {
+        let mut changes = [None; 4];
+        let color = self.side_to_move();
+        let (src, dest) = (mov.get_source(), mov.get_dest());
+        let piece = self
+            .piece_on(src)
+            .expect("a legal move always starts from a piece");
+
+        changes[0] = Some(PieceChange::Remove(piece, color, src));
+        changes[1] = Some(PieceChange::Add(
+            mov.get_promotion().unwrap_or(piece),
+            color,
+            dest,
+        ));
+
+        if let Some(captured) = self.piece_on(dest) {
+            changes[2] = Some(PieceChange::Remove(captured, !color, dest));
+        } else if piece == Piece::Pawn && src.get_file() != dest.get_file() {
+            // en passant, the captured pawn is next to the source square
+            let captured = Square::make_square(src.get_rank(), dest.get_file());
+            changes[2] = Some(PieceChange::Remove(Piece::Pawn, !color, captured));
+        }
+
+        let src_file = src.get_file().to_index();
+        let dest_file = dest.get_file().to_index();
+        if piece == Piece::King && src_file.abs_diff(dest_file) == 2 {
+            let rank = src.get_rank();
+            let (rook_from, rook_to) = if dest_file > src_file {
+                (File::H, File::F)
+            } else {
+                (File::A, File::D)
+            };
+            changes[2] = Some(PieceChange::Remove(
+                Piece::Rook,
+                color,
+                Square::make_square(rank, rook_from),
+            ));
+            changes[3] = Some(PieceChange::Add(
+                Piece::Rook,
+                color,
+                Square::make_square(rank, rook_to),
+            ));
+        }
+        changes
+    }
+}
+
 pub struct BoardMaterial {
     pub white: u32,
     pub black: u32,
@@ -96,7 +191,7 @@ extern crate test;
 mod tests {
     use std::str::FromStr;
 
-    use self::{consts::FEN_STRING, engine::Engine, eval::Evaluation};
+    use self::{consts::FEN_STRING, engine::Engine, eval::Evaluation, game_state::GameState};
 
     use super::*;
     use crate::MaterialSumExt;
@@ -106,7 +201,8 @@ mod tests {
     fn bench_eval_board(b: &mut Bencher) {
         b.iter(|| {
             let engine = Engine::from_str(&FEN_STRING[0]).unwrap();
-            Evaluation::new(&engine.board()).eval_board(engine.board(), engine.history());
+            Evaluation::new(&engine.board(), &mut GameState::new())
+                .eval_board(engine.board(), engine.history());
         })
     }
 
@@ -114,7 +210,7 @@ mod tests {
     fn bench_search_alpha_beta(b: &mut Bencher) {
         b.iter(|| {
             FEN_STRING.iter().take(3).for_each(|fen| {
-                Engine::from_str(fen).unwrap().search(3);
+                Engine::from_str(fen).unwrap().search(3, GameState::new());
             })
         });
     }
@@ -40,9 +40,43 @@ fn main() {
             "1. e4 c5 2. Nf3 Nc6 3. Bb5 e6 4. Bxc6 bxc6 5. d3 Ne7 6. Qe2 f6 7. Nh4 g6 8. f4 Bg7 9. O-O O-O",
             "1. e4 d5 2. exd5 Qxd5 3. Nc3 Qa5"
         ];
-        let mut opening_database = OpeningDatabase::new();
-        op.iter()
-            .for_each(|f| opening_database.add_png(f.to_string()));
+        // a book given on the command line replaces the built-in lines, with a second argument
+        // it is saved there as well: as a Polyglot book when the name ends in .bin, in the
+        // binary book format otherwise
+        let args = std::env::args().collect::<Vec<_>>();
+        let opening_database = match args.get(1) {
+            Some(path) if path.ends_with(".pgn") => {
+                OpeningDatabase::from_pgn_file(path, &BookOptions::default()).unwrap_or_else(
+                    |err| {
+                        println!("info string could not build the book from {path}: {err}");
+                        OpeningDatabase::new()
+                    },
+                )
+            }
+            Some(path) => OpeningDatabase::load(path).unwrap_or_else(|err| {
+                println!("info string could not load the book {path}: {err}");
+                OpeningDatabase::new()
+            }),
+            None => {
+                let mut opening_database = OpeningDatabase::new();
+                for pgn in op {
+                    if let Err(err) = opening_database.add_png(pgn) {
+                        println!("info string opening line not added: {err}");
+                    }
+                }
+                opening_database
+            }
+        };
+        if let Some(out) = args.get(2) {
+            let saved = if out.ends_with(".bin") {
+                PolyglotBook::from_opening_database(&opening_database).save(out)
+            } else {
+                opening_database.save(out)
+            };
+            if let Err(err) = saved {
+                println!("info string could not save the book to {out}: {err}");
+            }
+        }
         let mut uci = UCI::new();
         uci.add_db(opening_database);
         uci.rx();
@@ -0,0 +1,382 @@
+//! Efficiently updatable neural network evaluation.
+//!
+//! The network is a simple `768 -> H x 2 -> 1` perceptron. Every (colour, piece, square) triple
+//! is one input, seen once from white's side and once from black's side (black's view swaps the
+//! colours and flips the board vertically), so each side keeps its own accumulator of `H` values.
+//! The accumulators are updated incrementally when a move is made, and only the clipped
+//! accumulators times the output weights have to be computed at the leaves.
+//!
+//! # Weight file format
+//!
+//! All values are little endian.
+//!
+//! | offset              | size          | content                                            |
+//! |---------------------|---------------|----------------------------------------------------|
+//! | 0                   | 8             | magic `b"CENNUE01"`                                |
+//! | 8                   | 4             | `u32` hidden size `H` (1 ..= 4096)                 |
+//! | 12                  | 768 * H * 2   | `i16` input weights, the `H` weights of input 0, then input 1, ... |
+//! | 12 + 1536 H         | H * 2         | `i16` hidden biases                                |
+//! | 12 + 1538 H         | 2 * H * 2     | `i16` output weights, `H` for the side to move then `H` for the other side |
+//! | 12 + 1542 H         | 4             | `i32` output bias                                  |
+//!
+//! Input index for a piece, from the view of `perspective`:
+//! `relative_colour * 384 + piece * 64 + relative_square`, where `relative_colour` is 0 for the
+//! pieces of `perspective`, `piece` follows `chess::Piece::to_index` and `relative_square` is the
+//! square index flipped with `^ 56` for black's view.
+//!
+//! The hidden layer is clipped to `0 ..= QA` before the output layer, the output is divided by
+//! `QA * QB` and scaled by `SCALE` to give centipawns from the side to move.
+
+use std::{fmt::Display, fs, io, path::Path};
+
+use chess::{Board, ChessMove, Color, Piece, Square, ALL_PIECES};
+
+use crate::{MoveChangesExt, PieceChange};
+
+const MAGIC: &[u8; 8] = b"CENNUE01";
+const INPUTS: usize = 768;
+const MAX_HIDDEN: usize = 4096;
+const HEADER_SIZE: usize = 12;
+
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+#[derive(Debug)]
+pub enum NnueError {
+    Io(io::Error),
+    BadMagic,
+    TooShort,
+    BadHiddenSize(usize),
+    WrongLength { expected: usize, found: usize },
+}
+
+impl Display for NnueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NnueError::Io(err) => write!(f, "could not read network file: {err}"),
+            NnueError::BadMagic => write!(f, "not a network file (bad magic)"),
+            NnueError::TooShort => write!(f, "network file is too short for its header"),
+            NnueError::BadHiddenSize(size) => {
+                write!(f, "hidden size {size} is not within 1..={MAX_HIDDEN}")
+            }
+            NnueError::WrongLength { expected, found } => write!(
+                f,
+                "network file should be {expected} bytes for its hidden size but is {found}"
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for NnueError {
+    fn from(value: io::Error) -> Self {
+        NnueError::Io(value)
+    }
+}
+
+/// reads little endian values one after the other
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn i16s(&mut self, count: usize) -> Vec<i16> {
+        let end = self.position + count * 2;
+        let res = self.bytes[self.position..end]
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        self.position = end;
+        res
+    }
+
+    fn i32(&mut self) -> i32 {
+        let b = &self.bytes[self.position..self.position + 4];
+        self.position += 4;
+        i32::from_le_bytes([b[0], b[1], b[2], b[3]])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    hidden: usize,
+    input_weights: Vec<i16>,
+    hidden_bias: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network {
+    fn file_size(hidden: usize) -> usize {
+        HEADER_SIZE + INPUTS * hidden * 2 + hidden * 2 + 2 * hidden * 2 + 4
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, NnueError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NnueError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(NnueError::TooShort);
+        }
+        if &bytes[..8] != MAGIC {
+            return Err(NnueError::BadMagic);
+        }
+        let hidden = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+        if hidden == 0 || hidden > MAX_HIDDEN {
+            return Err(NnueError::BadHiddenSize(hidden));
+        }
+        let expected = Self::file_size(hidden);
+        if bytes.len() != expected {
+            return Err(NnueError::WrongLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let mut reader = Reader {
+            bytes,
+            position: HEADER_SIZE,
+        };
+        Ok(Self {
+            hidden,
+            input_weights: reader.i16s(INPUTS * hidden),
+            hidden_bias: reader.i16s(hidden),
+            output_weights: reader.i16s(2 * hidden),
+            output_bias: reader.i32(),
+        })
+    }
+
+    /// the inverse of `from_bytes`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(Self::file_size(self.hidden));
+        res.extend_from_slice(MAGIC);
+        res.extend_from_slice(&(self.hidden as u32).to_le_bytes());
+        for w in self
+            .input_weights
+            .iter()
+            .chain(self.hidden_bias.iter())
+            .chain(self.output_weights.iter())
+        {
+            res.extend_from_slice(&w.to_le_bytes());
+        }
+        res.extend_from_slice(&self.output_bias.to_le_bytes());
+        res
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden
+    }
+
+    fn feature(perspective: Color, piece: Piece, color: Color, square: Square) -> usize {
+        let (relative_color, relative_square) = match perspective {
+            Color::White => (color.to_index(), square.to_index()),
+            Color::Black => ((!color).to_index(), square.to_index() ^ 56),
+        };
+        relative_color * 384 + piece.to_index() * 64 + relative_square
+    }
+
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.input_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    /// evaluates an accumulator, returning centipawns from the view of `side_to_move`
+    pub fn evaluate(&self, acc: &Accumulator, side_to_move: Color) -> isize {
+        let (us, them) = match side_to_move {
+            Color::White => (&acc.white, &acc.black),
+            Color::Black => (&acc.black, &acc.white),
+        };
+        let (us_weights, them_weights) = self.output_weights.split_at(self.hidden);
+
+        // up to 2 * 4096 * 255 * 32768 before the bias, too much for an i32
+        let mut sum: i64 = 0;
+        for (v, w) in us.iter().zip(us_weights) {
+            sum += (*v as i32).clamp(0, QA) as i64 * *w as i64;
+        }
+        for (v, w) in them.iter().zip(them_weights) {
+            sum += (*v as i32).clamp(0, QA) as i64 * *w as i64;
+        }
+        ((sum + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64) as isize
+    }
+}
+
+/// The hidden layer before clipping, once from each side's view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accumulator {
+    white: Vec<i16>,
+    black: Vec<i16>,
+}
+
+impl Accumulator {
+    /// builds the accumulator from scratch for every piece on the board
+    pub fn refresh(net: &Network, board: &Board) -> Self {
+        let mut acc = Self {
+            white: net.hidden_bias.clone(),
+            black: net.hidden_bias.clone(),
+        };
+        for piece in ALL_PIECES {
+            for color in [Color::White, Color::Black] {
+                let bb = board.pieces(piece) & board.color_combined(color);
+                for square in bb {
+                    acc.add(net, piece, color, square);
+                }
+            }
+        }
+        acc
+    }
+
+    fn add(&mut self, net: &Network, piece: Piece, color: Color, square: Square) {
+        let white = net.weights(Network::feature(Color::White, piece, color, square));
+        self.white
+            .iter_mut()
+            .zip(white)
+            .for_each(|(a, w)| *a = a.wrapping_add(*w));
+        let black = net.weights(Network::feature(Color::Black, piece, color, square));
+        self.black
+            .iter_mut()
+            .zip(black)
+            .for_each(|(a, w)| *a = a.wrapping_add(*w));
+    }
+
+    fn remove(&mut self, net: &Network, piece: Piece, color: Color, square: Square) {
+        let white = net.weights(Network::feature(Color::White, piece, color, square));
+        self.white
+            .iter_mut()
+            .zip(white)
+            .for_each(|(a, w)| *a = a.wrapping_sub(*w));
+        let black = net.weights(Network::feature(Color::Black, piece, color, square));
+        self.black
+            .iter_mut()
+            .zip(black)
+            .for_each(|(a, w)| *a = a.wrapping_sub(*w));
+    }
+
+    /// returns the accumulator after `mov` is played on `board`, only touching the inputs that
+    /// change
+    pub fn apply_move(&self, net: &Network, board: &Board, mov: ChessMove) -> Self {
+        let mut acc = self.clone();
+        for change in board.move_changes(mov).into_iter().flatten() {
+            match change {
+                PieceChange::Add(piece, color, square) => acc.add(net, piece, color, square),
+                PieceChange::Remove(piece, color, square) => acc.remove(net, piece, color, square),
+            }
+        }
+        acc
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chess::{Board, ChessMove};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn random_network(hidden: usize) -> Network {
+        let mut rng = StdRng::seed_from_u64(7);
+        Network {
+            hidden,
+            input_weights: (0..INPUTS * hidden)
+                .map(|_| rng.gen_range(-64..64))
+                .collect(),
+            hidden_bias: (0..hidden).map(|_| rng.gen_range(-64..64)).collect(),
+            output_weights: (0..2 * hidden).map(|_| rng.gen_range(-64..64)).collect(),
+            output_bias: rng.gen_range(-1000..1000),
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let net = random_network(8);
+        assert_eq!(Network::from_bytes(&net.to_bytes()).unwrap(), net);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let bytes = random_network(8).to_bytes();
+        assert!(matches!(
+            Network::from_bytes(&bytes[..6]),
+            Err(NnueError::TooShort)
+        ));
+        assert!(matches!(
+            Network::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(NnueError::WrongLength { .. })
+        ));
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            Network::from_bytes(&bad_magic),
+            Err(NnueError::BadMagic)
+        ));
+
+        let mut bad_size = bytes;
+        bad_size[8..12].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            Network::from_bytes(&bad_size),
+            Err(NnueError::BadHiddenSize(0))
+        ));
+    }
+
+    #[test]
+    fn extreme_weights_do_not_overflow() {
+        let hidden = MAX_HIDDEN;
+        let net = Network {
+            hidden,
+            input_weights: vec![0; INPUTS * hidden],
+            hidden_bias: vec![i16::MAX; hidden],
+            output_weights: vec![i16::MAX; 2 * hidden],
+            output_bias: i32::MAX,
+        };
+        let acc = Accumulator::refresh(&net, &Board::default());
+        let expected = (2 * hidden as i64 * QA as i64 * i16::MAX as i64 + i32::MAX as i64)
+            * SCALE as i64
+            / (QA * QB) as i64;
+        assert_eq!(net.evaluate(&acc, Color::White), expected as isize);
+    }
+
+    #[test]
+    fn incremental_update_matches_refresh() {
+        let net = random_network(16);
+        // castling, en passant, promotion with capture and plain captures
+        let lines = [
+            (
+                "r3k2r/pppq1ppp/2np1n2/2b1p3/2B1P1b1/2NP1N2/PPPQ1PPP/R3K2R w KQkq - 0 1",
+                vec!["e1g1", "e8c8", "c3d5", "f6d5"],
+            ),
+            (
+                "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+                vec!["e5d6", "e8f7", "d6d7", "f7e7", "d7d8q"],
+            ),
+            ("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1", vec!["a7b8q", "e8d7"]),
+        ];
+
+        for (fen, moves) in lines {
+            let mut board = Board::from_str(fen).unwrap();
+            let mut acc = Accumulator::refresh(&net, &board);
+            for mov in moves {
+                let mov = ChessMove::from_str(mov).unwrap();
+                assert!(board.legal(mov), "{mov} on {board}");
+                acc = acc.apply_move(&net, &board, mov);
+                board = board.make_move_new(mov);
+                assert_eq!(acc, Accumulator::refresh(&net, &board));
+            }
+        }
+    }
+
+    #[test]
+    fn evaluation_is_colour_symmetric() {
+        let net = random_network(16);
+        let board = Board::from_str("4k3/8/8/3p4/8/8/8/4K3 b - - 0 1").unwrap();
+        let mirrored = Board::from_str("4k3/8/8/8/3P4/8/8/4K3 w - - 0 1").unwrap();
+        let acc = Accumulator::refresh(&net, &board);
+        let mirrored_acc = Accumulator::refresh(&net, &mirrored);
+        assert_eq!(
+            net.evaluate(&acc, board.side_to_move()),
+            net.evaluate(&mirrored_acc, mirrored.side_to_move())
+        );
+    }
+}
@@ -1,154 +1,691 @@
-use std::str::FromStr;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+};
 
-use chess::{Board, ChessMove};
+use chess::{Board, ChessMove, Color, Piece, ALL_SQUARES};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    Rng,
+};
 
-use crate::trie::*;
+use crate::{
+    pgn::{parse_games, GameResult, PgnError, PgnGame, PgnReader},
+    trie::*,
+};
 
-#[derive(Clone)]
-pub struct OpeningDatabase {
-    opening_lines: Trie<ChessMove>,
+/// a line of the opening book, see `OpeningDatabase::children`
+pub type BookCursor = TrieCursor<ChessMove>;
+
+/// plies of each game that go into a book built from a collection
+pub const DEFAULT_BOOK_PLIES: usize = 24;
+
+/// first bytes of a binary book file
+const BOOK_MAGIC: [u8; 4] = *b"NSBB";
+/// the binary book format written by this version, older ones are refused
+pub const BOOK_VERSION: u16 = 2;
+
+#[derive(Debug)]
+pub enum BookError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    Corrupt(TrieError),
+    /// a line of the book plays a move that is not legal in the position it reaches
+    IllegalMove {
+        fen: String,
+        mov: ChessMove,
+    },
 }
 
-impl OpeningDatabase {
-    pub fn is_end(&self) -> bool {
-        self.opening_lines.is_end()
+impl Display for BookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BookError::Io(err) => write!(f, "could not read book file: {err}"),
+            BookError::BadMagic => write!(f, "not a book file"),
+            BookError::UnsupportedVersion(version) => {
+                write!(f, "book file version {version}, expected {BOOK_VERSION}")
+            }
+            BookError::Corrupt(err) => write!(f, "corrupt book file: {err}"),
+            BookError::IllegalMove { fen, mov } => {
+                write!(f, "corrupt book file: illegal move {mov} in {fen}")
+            }
+        }
     }
-    pub fn new() -> Self {
-        Self {
-            opening_lines: Trie::default(),
+}
+
+impl std::error::Error for BookError {}
+
+impl From<io::Error> for BookError {
+    fn from(value: io::Error) -> Self {
+        BookError::Io(value)
+    }
+}
+
+impl From<TrieError> for BookError {
+    fn from(value: TrieError) -> Self {
+        BookError::Corrupt(value)
+    }
+}
+
+/// an average evaluation at book exit below this, in centipawns for the side that played the
+/// move, marks a book move as leading to a bad position
+pub const BAD_EXIT_EVAL: isize = -100;
+/// evaluations learned at book exit are clamped to this so a mate does not swamp the average
+const MAX_EXIT_EVAL: isize = 2_000;
+
+/// how the games that played a move went, for the side that played it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MoveStats {
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// games the engine learned an evaluation at book exit from
+    pub exit_evals: u32,
+    /// sum of those evaluations in centipawns
+    pub exit_eval_sum: i64,
+}
+
+impl MoveStats {
+    pub fn record(&mut self, result: GameResult, mover: Color) {
+        self.games += 1;
+        match (result, mover) {
+            (GameResult::WhiteWins, Color::White) | (GameResult::BlackWins, Color::Black) => {
+                self.wins += 1
+            }
+            (GameResult::WhiteWins, Color::Black) | (GameResult::BlackWins, Color::White) => {
+                self.losses += 1
+            }
+            (GameResult::Draw, _) => self.draws += 1,
+            (GameResult::Unknown, _) => {}
         }
     }
 
-    pub fn choose_opening_move(&mut self, chess_move: ChessMove) -> bool {
-        self.opening_lines.change_root(chess_move)
+    /// points per decided game for the side that played the move, `None` if no result is known
+    pub fn score(&self) -> Option<f64> {
+        let decided = self.wins + self.draws + self.losses;
+        (decided > 0).then(|| (self.wins as f64 + self.draws as f64 / 2.0) / decided as f64)
     }
 
-    pub fn add_png(&mut self, pgn: String) {
-        let mut moves = vec![];
-        let mut parser = PgnParser::new(pgn);
-        let mut board = Board::default();
-        while let Ok((white, black)) = parser.next_token() {
-            let white_move = ChessMove::from_san(&board, &white).unwrap();
-            board = board.make_move_new(white_move);
-            moves.push(white_move);
+    pub fn record_exit_eval(&mut self, eval: isize) {
+        self.exit_evals += 1;
+        self.exit_eval_sum += eval.clamp(-MAX_EXIT_EVAL, MAX_EXIT_EVAL) as i64;
+    }
 
-            let black_move = ChessMove::from_san(&board, &black).unwrap();
-            board = board.make_move_new(black_move);
-            moves.push(black_move);
+    /// the average evaluation where the engine left the book after this move, `None` before it
+    /// has learned any
+    pub fn exit_eval(&self) -> Option<f64> {
+        (self.exit_evals > 0).then(|| self.exit_eval_sum as f64 / self.exit_evals as f64)
+    }
+
+    /// whether the engine learned the move leads to a bad position
+    pub fn is_bad(&self) -> bool {
+        self.exit_eval()
+            .is_some_and(|eval| eval < BAD_EXIT_EVAL as f64)
+    }
+}
+
+/// Source square in bits 6 to 11 and destination in bits 0 to 5 like Polyglot, the promotion
+/// piece from knight as 1 to queen as 4 in bits 12 to 14.
+impl TrieCodec for ChessMove {
+    const SIZE: usize = 2;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        let promotion = match self.get_promotion() {
+            Some(Piece::Knight) => 1,
+            Some(Piece::Bishop) => 2,
+            Some(Piece::Rook) => 3,
+            Some(Piece::Queen) => 4,
+            _ => 0,
+        };
+        let raw = (promotion << 12)
+            | ((self.get_source().to_index() as u16) << 6)
+            | self.get_dest().to_index() as u16;
+        out.extend(raw.to_be_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let raw = u16::from_be_bytes(bytes.try_into().ok()?);
+        let promotion = match raw >> 12 {
+            0 => None,
+            1 => Some(Piece::Knight),
+            2 => Some(Piece::Bishop),
+            3 => Some(Piece::Rook),
+            4 => Some(Piece::Queen),
+            _ => return None,
+        };
+        let source = ALL_SQUARES[(raw >> 6 & 63) as usize];
+        let dest = ALL_SQUARES[(raw & 63) as usize];
+        (source != dest).then(|| ChessMove::new(source, dest, promotion))
+    }
+}
+
+/// the five counts as big-endian `u32` and then the evaluation sum as `i64`
+impl TrieCodec for MoveStats {
+    const SIZE: usize = 28;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        for count in [
+            self.games,
+            self.wins,
+            self.draws,
+            self.losses,
+            self.exit_evals,
+        ] {
+            out.extend(count.to_be_bytes());
         }
-        self.opening_lines.insert(&moves);
+        out.extend(self.exit_eval_sum.to_be_bytes());
     }
 
-    pub fn root(&self) -> &Node<ChessMove> {
-        self.opening_lines.root()
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let count = |i: usize| {
+            Some(u32::from_be_bytes(
+                bytes.get(4 * i..4 * i + 4)?.try_into().ok()?,
+            ))
+        };
+        let stats = MoveStats {
+            games: count(0)?,
+            wins: count(1)?,
+            draws: count(2)?,
+            losses: count(3)?,
+            exit_evals: count(4)?,
+            exit_eval_sum: i64::from_be_bytes(bytes.get(20..28)?.try_into().ok()?),
+        };
+        let decided = stats.wins as u64 + stats.draws as u64 + stats.losses as u64;
+        let max_sum = stats.exit_evals as i64 * MAX_EXIT_EVAL as i64;
+        (decided <= stats.games as u64
+            && stats.exit_evals <= stats.games
+            && stats.exit_eval_sum.unsigned_abs() <= max_sum as u64)
+            .then_some(stats)
     }
+}
 
-    pub fn print(&self) {
-        self.opening_lines.print()
+/// how the engine picks among the book moves of a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BookPolicy {
+    /// the best expected score, counting two extra wins and two extra losses so a single won game
+    /// does not outrank a line played a thousand times
+    #[default]
+    Best,
+    /// at random, as often as the games played the move
+    Frequency,
+    /// at random, as often as the points the move scored
+    Score,
+    /// at random, every move alike
+    Uniform,
+}
+
+impl FromStr for BookPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "best" => Ok(BookPolicy::Best),
+            "frequency" => Ok(BookPolicy::Frequency),
+            "score" => Ok(BookPolicy::Score),
+            "uniform" => Ok(BookPolicy::Uniform),
+            _ => Err(format!("unknown book policy {s}")),
+        }
+    }
+}
+
+impl Display for BookPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            BookPolicy::Best => "best",
+            BookPolicy::Frequency => "frequency",
+            BookPolicy::Score => "score",
+            BookPolicy::Uniform => "uniform",
+        };
+        write!(f, "{name}")
     }
 }
-trait ToChar {
-    fn to_char(&self) -> char;
+
+impl BookPolicy {
+    /// One of the book `moves` with their statistics, drawn by `probabilities`.
+    pub fn choose<R: Rng>(
+        self,
+        moves: &[(ChessMove, MoveStats)],
+        rng: &mut R,
+    ) -> Option<ChessMove> {
+        self.draw(self.probabilities(moves), rng)
+    }
+
+    /// The chance of each of the book `moves` to be chosen, they add up to 1. Moves learned to
+    /// lead to a bad position are only played when all of them do.
+    pub fn probabilities(self, moves: &[(ChessMove, MoveStats)]) -> Vec<(ChessMove, f64)> {
+        let all_bad = moves.iter().all(|(_, stats)| stats.is_bad());
+        let usable = |stats: &MoveStats| all_bad || !stats.is_bad();
+        let points = |stats: &MoveStats| stats.wins as f64 + stats.draws as f64 / 2.0;
+        let best = moves
+            .iter()
+            .filter(|(_, stats)| usable(stats))
+            .max_by(|(_, a), (_, b)| {
+                let expected = |stats: &MoveStats| {
+                    let decided = stats.wins + stats.draws + stats.losses;
+                    (points(stats) + 2.0) / (decided as f64 + 4.0)
+                };
+                expected(a)
+                    .total_cmp(&expected(b))
+                    .then(a.games.cmp(&b.games))
+            })
+            .map(|(mov, _)| *mov);
+
+        let weights = moves
+            .iter()
+            .map(|(mov, stats)| {
+                let weight = match self {
+                    _ if !usable(stats) => 0.0,
+                    BookPolicy::Best => (best == Some(*mov)) as u8 as f64,
+                    BookPolicy::Frequency => stats.games as f64,
+                    BookPolicy::Score => points(stats),
+                    BookPolicy::Uniform => 1.0,
+                };
+                (*mov, weight)
+            })
+            .collect();
+        match normalise(weights) {
+            Some(probabilities) => probabilities,
+            None => BookPolicy::Uniform.probabilities(moves),
+        }
+    }
+
+    /// one of the book `moves` that only come with a weight, like those of a Polyglot book
+    pub fn choose_weighted<R: Rng>(
+        self,
+        moves: &[(ChessMove, u16)],
+        rng: &mut R,
+    ) -> Option<ChessMove> {
+        self.draw(self.probabilities_weighted(moves), rng)
+    }
+
+    /// the chance of each of the weighted book `moves` to be chosen, they add up to 1
+    pub fn probabilities_weighted(self, moves: &[(ChessMove, u16)]) -> Vec<(ChessMove, f64)> {
+        let best = moves
+            .iter()
+            .max_by_key(|(_, weight)| *weight)
+            .map(|(mov, _)| *mov);
+        let weights = moves
+            .iter()
+            .map(|(mov, weight)| {
+                let weight = match self {
+                    BookPolicy::Best => (best == Some(*mov)) as u8 as f64,
+                    BookPolicy::Frequency | BookPolicy::Score => *weight as f64,
+                    BookPolicy::Uniform => 1.0,
+                };
+                (*mov, weight)
+            })
+            .collect();
+        match normalise(weights) {
+            Some(probabilities) => probabilities,
+            None => BookPolicy::Uniform.probabilities_weighted(moves),
+        }
+    }
+
+    /// the best policy always plays its most likely move, the others draw at random
+    fn draw<R: Rng>(self, moves: Vec<(ChessMove, f64)>, rng: &mut R) -> Option<ChessMove> {
+        match self {
+            BookPolicy::Best => moves
+                .iter()
+                .find(|(_, chance)| *chance > 0.0)
+                .map(|(mov, _)| *mov),
+            _ => WeightedIndex::new(moves.iter().map(|(_, chance)| *chance))
+                .ok()
+                .map(|index| moves[index.sample(rng)].0),
+        }
+    }
 }
 
-impl ToChar for u8 {
-    fn to_char(&self) -> char {
-        char::from_u32(*self as u32).expect("Expected UTF-8")
+/// scales `weights` to add up to 1, `None` when they are all zero
+fn normalise(mut weights: Vec<(ChessMove, f64)>) -> Option<Vec<(ChessMove, f64)>> {
+    let total = weights.iter().map(|(_, weight)| weight).sum::<f64>();
+    if total <= 0.0 && !weights.is_empty() {
+        return None;
     }
+    weights.iter_mut().for_each(|(_, weight)| *weight /= total);
+    Some(weights)
 }
 
-#[derive(Debug)]
-enum ParseError {
-    NoValidChar,
-    InvalidMove,
+/// which games of a collection go into the book and how much of them
+#[derive(Debug, Clone)]
+pub struct BookOptions {
+    pub max_plies: usize,
+    /// both players need at least this rating
+    pub min_elo: Option<u32>,
+    /// the results a game may have, any result if empty
+    pub results: Vec<GameResult>,
+    /// prefixes of the ECO codes a game may have, like `B` or `C6`, any if empty
+    pub eco: Vec<String>,
 }
 
-struct PgnParser {
-    input: Vec<u8>,
-    position: usize,
-    read_position: usize,
-    char: u8,
-    eof: bool,
+impl Default for BookOptions {
+    fn default() -> Self {
+        Self {
+            max_plies: DEFAULT_BOOK_PLIES,
+            min_elo: None,
+            results: vec![],
+            eco: vec![],
+        }
+    }
 }
 
-impl PgnParser {
-    fn new<T: ToString>(input: T) -> Self {
-        let input = input.to_string().as_bytes().to_vec();
-        let mut res = Self {
-            input,
-            position: 0,
-            read_position: 0,
-            char: 0,
-            eof: false,
+impl BookOptions {
+    pub fn with_max_plies(mut self, max_plies: usize) -> Self {
+        self.max_plies = max_plies;
+        self
+    }
+
+    pub fn with_min_elo(mut self, min_elo: u32) -> Self {
+        self.min_elo = Some(min_elo);
+        self
+    }
+
+    pub fn with_results(mut self, results: &[GameResult]) -> Self {
+        self.results = results.to_vec();
+        self
+    }
+
+    pub fn with_eco(mut self, eco: &[&str]) -> Self {
+        self.eco = eco.iter().map(|eco| eco.to_string()).collect();
+        self
+    }
+
+    /// whether `game` passes the header filters, a missing or unreadable header fails them
+    pub fn accepts(&self, game: &PgnGame) -> bool {
+        let elo = |tag| {
+            game.header(tag)
+                .and_then(|elo| elo.trim().parse::<u32>().ok())
         };
-        res.read_char();
-        res
+        let elo_ok = self.min_elo.map_or(true, |min| {
+            elo("WhiteElo").is_some_and(|elo| elo >= min)
+                && elo("BlackElo").is_some_and(|elo| elo >= min)
+        });
+        let result_ok = self.results.is_empty() || self.results.contains(&game.result);
+        let eco_ok = self.eco.is_empty()
+            || game
+                .header("ECO")
+                .is_some_and(|code| self.eco.iter().any(|eco| code.starts_with(eco.as_str())));
+        elo_ok && result_ok && eco_ok
     }
+}
 
-    pub(crate) fn read_char(&mut self) {
-        if self.read_position >= self.input.len() {
-            // Checking if the next position is
-            // end of the file or exceeded the
-            // file1. e4 Nc6 2. c4 e5 3. Nc3 Nf6 4. Be2 Bc5 5. Nf3 h6 6. a3 d5 7. cxd5 Bg4 8. b4 Be7 9. h3 Bh5 10. g4 Bg6 11. Qb3 Qd6 12. O-O O-O
-            self.char = 0; // setting it to Zero to indicate NULL or EOF
-            self.eof = true;
-        } else {
-            self.char = self.input[self.read_position];
+#[derive(Clone, Default)]
+pub struct OpeningDatabase {
+    /// the lines from the start position
+    opening_lines: Trie<ChessMove, MoveStats>,
+    /// the book moves of every position by `Board::get_hash`, whatever the move order that led
+    /// there or the position the game was set up from
+    positions: HashMap<u64, HashMap<ChessMove, MoveStats>>,
+}
+
+impl OpeningDatabase {
+    pub fn new() -> Self {
+        Self {
+            opening_lines: Trie::default(),
+            positions: HashMap::new(),
         }
-        self.position = self.read_position; // Advancing the position
-        self.read_position = self.read_position.wrapping_add(1); // Advancing the read_position
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.char.to_char().is_whitespace() {
-            self.read_char();
+    /// the book moves that go on from the line at `cursor`, the most played first
+    pub fn children(&self, cursor: &BookCursor) -> Vec<(ChessMove, MoveStats)> {
+        let Some(node) = cursor.node(&self.opening_lines) else {
+            return vec![];
+        };
+        let mut moves = node
+            .children()
+            .iter()
+            .map(|(mov, node)| (*mov, *node.payload()))
+            .collect::<Vec<_>>();
+        moves.sort_by_cached_key(|(mov, stats)| (Reverse(stats.games), mov.to_string()));
+        moves
+    }
+
+    /// whether the line at `cursor` is still in the book
+    pub fn in_book(&self, cursor: &BookCursor) -> bool {
+        cursor.node(&self.opening_lines).is_some()
+    }
+
+    /// Streams the games of a PGN file into a new book. The first invalid game stops it with an
+    /// error naming its line.
+    pub fn from_pgn_file<P: AsRef<Path>>(path: P, options: &BookOptions) -> Result<Self, PgnError> {
+        Self::from_pgn_reader(BufReader::new(File::open(path)?), options)
+    }
+
+    pub fn from_pgn_reader<R: BufRead>(reader: R, options: &BookOptions) -> Result<Self, PgnError> {
+        let mut db = Self::new();
+        for game in PgnReader::new(reader) {
+            let game = game?;
+            if options.accepts(&game) {
+                db.add_game(&game, options.max_plies)?;
+            }
         }
+        Ok(db)
     }
-    fn is_letter(&self) -> bool {
-        self.char.to_char().is_alphabetic()
+
+    /// Adds the first `max_plies` moves of the main line and records the result for each of
+    /// them. Games set up from a FEN only go into the positions, the lines all start from the
+    /// normal start position.
+    pub fn add_game(&mut self, game: &PgnGame, max_plies: usize) -> Result<(), PgnError> {
+        let line = game.mainline();
+        let line = &line[..line.len().min(max_plies)];
+        self.add_line(game.start_board()?, line, Some(game.result));
+        Ok(())
     }
 
-    fn read_move(&mut self) -> String {
-        self.skip_whitespace();
-        let mut res = String::new();
-        while !self.char.to_char().is_whitespace() {
-            res.push(self.char.to_char());
-            self.read_char();
-            if self.eof {
-                break;
+    /// Adds every game in `pgn` with its variations, which have no results to record. Nothing
+    /// is added if any game is invalid.
+    pub fn add_png(&mut self, pgn: &str) -> Result<(), PgnError> {
+        let games = parse_games(pgn)?;
+        for game in games.iter() {
+            self.add_game(game, usize::MAX)?;
+            let start = game.start_board()?;
+            for line in game.lines() {
+                self.add_line(start, &line, None);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_line(&mut self, start: Board, line: &[ChessMove], result: Option<GameResult>) {
+        let mut board = start;
+        for mov in line {
+            let stats = self
+                .positions
+                .entry(board.get_hash())
+                .or_default()
+                .entry(*mov)
+                .or_default();
+            if let Some(result) = result {
+                stats.record(result, board.side_to_move());
+            }
+            board = board.make_move_new(*mov);
+        }
+
+        if start != Board::default() || line.is_empty() {
+            return;
+        }
+        match result {
+            Some(result) => {
+                let mut mover = Color::White;
+                self.opening_lines.insert_with(line, |stats| {
+                    stats.record(result, mover);
+                    mover = !mover;
+                });
             }
+            None => self.opening_lines.insert(line),
         }
-        res
     }
 
-    // (e4, e5)
-    pub(crate) fn next_token(&mut self) -> Result<(String, String), ParseError> {
-        self.skip_whitespace();
-        let mut res = Err(ParseError::NoValidChar);
+    /// the legal book moves of `board` however it was reached, the most played first
+    pub fn moves(&self, board: &Board) -> Vec<(ChessMove, MoveStats)> {
+        let Some(moves) = self.positions.get(&board.get_hash()) else {
+            return vec![];
+        };
+        let mut moves = moves
+            .iter()
+            .filter(|(mov, _)| board.legal(**mov))
+            .map(|(mov, stats)| (*mov, *stats))
+            .collect::<Vec<_>>();
+        // the move itself breaks ties so the order does not depend on the hash map
+        moves.sort_by_cached_key(|(mov, stats)| (Reverse(stats.games), mov.to_string()));
+        moves
+    }
+
+    /// the statistics of the last move of `line`, played from the current book position
+    pub fn stats(&self, line: &[ChessMove]) -> Option<MoveStats> {
+        self.opening_lines
+            .node(line)
+            .filter(|_| !line.is_empty())
+            .map(|node| *node.payload())
+    }
+
+    pub fn root(&self) -> &Node<ChessMove, MoveStats> {
+        self.opening_lines.root()
+    }
 
-        loop {
-            if self.eof {
-                return Err(ParseError::NoValidChar);
+    /// Learns from a game the engine played from `start`: every move of the game still in the
+    /// book records `result` and the evaluation where the game left the book, in centipawns
+    /// for White, so lines that went badly are played less. Returns how many moves it learned.
+    pub fn learn(
+        &mut self,
+        start: Board,
+        moves: &[ChessMove],
+        result: GameResult,
+        exit_eval: Option<isize>,
+    ) -> usize {
+        let learn = |stats: &mut MoveStats, mover: Color| {
+            stats.record(result, mover);
+            if let Some(eval) = exit_eval {
+                stats.record_exit_eval(match mover {
+                    Color::White => eval,
+                    Color::Black => -eval,
+                });
             }
-            let curr = self.char.to_char();
-            if self.is_letter() {
-                let left_move = self.read_move();
-                let right_move = self.read_move();
-                res = Ok((left_move, right_move));
+        };
+
+        let mut board = start;
+        let mut learned = 0;
+        for mov in moves {
+            let Some(stats) = self
+                .positions
+                .get_mut(&board.get_hash())
+                .and_then(|moves| moves.get_mut(mov))
+            else {
                 break;
+            };
+            learn(stats, board.side_to_move());
+            if start == Board::default() {
+                if let Some(node) = self.opening_lines.node_mut(&moves[..=learned]) {
+                    learn(node.payload_mut(), board.side_to_move());
+                }
             }
-            if curr.is_ascii_digit() || curr == '.' || curr == ' ' {
-                self.read_char();
+            board = board.make_move_new(*mov);
+            learned += 1;
+        }
+        learned
+    }
+
+    /// Reads a book saved with `save`, much faster than building it from the games again.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, BookError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Checks every part of the file, a corrupt one is an error naming the offset of the bad
+    /// bytes. Lines longer than `MAX_DEPTH` plies or with an illegal move are refused as well.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        let mut reader = ByteReader::new(bytes);
+        if reader.take(BOOK_MAGIC.len()).ok() != Some(&BOOK_MAGIC[..]) {
+            return Err(BookError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != BOOK_VERSION {
+            return Err(BookError::UnsupportedVersion(version));
+        }
+
+        let opening_lines: Trie<ChessMove, MoveStats> = Trie::decode(&mut reader)?;
+        let mut stack = vec![(Board::default(), opening_lines.root())];
+        while let Some((board, node)) = stack.pop() {
+            for (mov, child) in node.children() {
+                if !board.legal(*mov) {
+                    return Err(BookError::IllegalMove {
+                        fen: board.to_string(),
+                        mov: *mov,
+                    });
+                }
+                stack.push((board.make_move_new(*mov), child));
             }
         }
-        self.read_char();
-        res
+
+        let mut positions = HashMap::new();
+        for _ in 0..reader.u32()? {
+            let offset = reader.offset();
+            let key = reader.u64()?;
+            let count = reader.u16()?;
+            let mut moves = HashMap::with_capacity(count as usize);
+            for _ in 0..count {
+                let offset = reader.offset();
+                let mov = reader.item()?;
+                if moves.insert(mov, reader.payload()?).is_some() {
+                    return Err(TrieError::Duplicate { offset }.into());
+                }
+            }
+            if positions.insert(key, moves).is_some() {
+                return Err(TrieError::Duplicate { offset }.into());
+            }
+        }
+        reader.finish()?;
+
+        Ok(Self {
+            opening_lines,
+            positions,
+        })
+    }
+
+    /// The header with the format version, the lines from the start position and then the
+    /// book moves of every position, games set up from a FEN included.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = BOOK_MAGIC.to_vec();
+        out.extend(BOOK_VERSION.to_be_bytes());
+        self.opening_lines.encode(&mut out);
+
+        out.extend((self.positions.len() as u32).to_be_bytes());
+        // sorted so the same book always gives the same file
+        let mut positions = self.positions.iter().collect::<Vec<_>>();
+        positions.sort_by_key(|(key, _)| **key);
+        for (key, moves) in positions {
+            out.extend(key.to_be_bytes());
+            out.extend((moves.len() as u16).to_be_bytes());
+            let mut moves = moves.iter().collect::<Vec<_>>();
+            moves.sort_by_key(|(mov, _)| mov.to_string());
+            for (mov, stats) in moves {
+                mov.encode(&mut out);
+                stats.encode(&mut out);
+            }
+        }
+        out
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn print(&self) {
+        self.opening_lines.print()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
     use super::*;
+    use crate::test_util::line;
 
     #[test]
     fn test_opening() {
@@ -156,7 +693,333 @@ mod tests {
             "1.e4 c6 2.d4 d5 3.e5 Bf5 4.Bd3 Bxd3 5.Qxd3 e6 6.f4 c5 7.c3 Nc6 8.Nf3 Qb6 9.O-O Nh6",
         );
         let mut db = OpeningDatabase::new();
-        db.add_png(test_in);
+        db.add_png(&test_in).unwrap();
         db.print();
+        let mut cursor = BookCursor::new();
+        for mov in ["e2e4", "c7c6"] {
+            let mov = ChessMove::from_str(mov).unwrap();
+            assert_eq!(db.children(&cursor)[0].0, mov);
+            cursor.push(mov);
+        }
+        assert!(db.in_book(&cursor));
+    }
+
+    #[test]
+    fn games_with_variations() {
+        let mut db = OpeningDatabase::new();
+        db.add_png("[Event \"?\"]\n\n1. e4 e5 (1... c5 2. Nf3) 2. Nf3 Nc6 3. Bb5 1-0")
+            .unwrap();
+        let mut cursor = BookCursor::from_items(&line(&["e2e4"]));
+        let replies = db.children(&cursor);
+        // the main line has the game, the variation none
+        assert_eq!(
+            replies
+                .iter()
+                .map(|(mov, stats)| (mov.to_string(), stats.games))
+                .collect::<Vec<_>>(),
+            vec![("e7e5".to_string(), 1), ("c7c5".to_string(), 0)]
+        );
+        cursor.push(line(&["c7c5"])[0]);
+        assert_eq!(
+            db.children(&cursor),
+            vec![(line(&["g1f3"])[0], MoveStats::default())]
+        );
+        cursor.pop();
+        cursor.push(line(&["e7e5"])[0]);
+        assert!(db.in_book(&cursor));
+
+        // an invalid game is reported and leaves the book as it was
+        let mut db = OpeningDatabase::new();
+        assert!(db.add_png("1. e4 e5 2. Ke3").is_err());
+        assert!(db.root().children().is_empty());
+    }
+
+    const COLLECTION: &str = r#"[White "A"]
+[Black "B"]
+[WhiteElo "2500"]
+[BlackElo "2400"]
+[ECO "C60"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1-0
+
+[White "C"]
+[Black "D"]
+[WhiteElo "2100"]
+[BlackElo "2600"]
+[ECO "C50"]
+[Result "0-1"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 0-1
+
+[White "E"]
+[Black "F"]
+[WhiteElo "2700"]
+[BlackElo "2650"]
+[ECO "B20"]
+[Result "1/2-1/2"]
+
+1. e4 c5 1/2-1/2
+
+[FEN "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"]
+[Result "1-0"]
+
+1. e4 1-0
+"#;
+
+    #[test]
+    fn statistics_from_a_collection() {
+        let options = BookOptions::default().with_max_plies(4);
+        let db = OpeningDatabase::from_pgn_reader(COLLECTION.as_bytes(), &options).unwrap();
+
+        let e4 = db.stats(&line(&["e2e4"])).unwrap();
+        assert_eq!(
+            e4,
+            MoveStats {
+                games: 3,
+                wins: 1,
+                draws: 1,
+                losses: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(e4.score(), Some(0.5));
+        // black's move counts the results from black's side
+        let e5 = db.stats(&line(&["e2e4", "e7e5"])).unwrap();
+        assert_eq!((e5.wins, e5.losses), (1, 1));
+        // truncated after four plies
+        assert!(db.stats(&line(&["e2e4", "e7e5", "g1f3", "b8c6"])).is_some());
+        assert!(db
+            .stats(&line(&["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"]))
+            .is_none());
+    }
+
+    #[test]
+    fn positions_from_any_move_order() {
+        let db = OpeningDatabase::from_pgn_reader(COLLECTION.as_bytes(), &BookOptions::default())
+            .unwrap();
+        let after = |moves: &[&str]| {
+            line(moves)
+                .into_iter()
+                .fold(Board::default(), |board, mov| board.make_move_new(mov))
+        };
+
+        // 1. Nf3 Nc6 2. e4 e5 reaches the position after 2. Nf3 Nc6
+        let moves = db.moves(&after(&["g1f3", "b8c6", "e2e4", "e7e5"]));
+        let (bb5, c4) = (line(&["f1b5"])[0], line(&["f1c4"])[0]);
+        assert_eq!(
+            moves.iter().map(|(mov, _)| *mov).collect::<Vec<_>>(),
+            vec![bb5, c4]
+        );
+        assert_eq!(moves[0].1.wins, 1);
+
+        // the game set up from a FEN is found by its position, it is not a line of the trie
+        let fen = Board::from_str("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            db.moves(&fen),
+            vec![(
+                line(&["e2e4"])[0],
+                MoveStats {
+                    games: 1,
+                    wins: 1,
+                    draws: 0,
+                    losses: 0,
+                    ..Default::default()
+                }
+            )]
+        );
+        assert_eq!(db.stats(&line(&["e2e4"])).unwrap().games, 3);
+    }
+
+    #[test]
+    fn binary_books() {
+        let db = OpeningDatabase::from_pgn_reader(COLLECTION.as_bytes(), &BookOptions::default())
+            .unwrap();
+        let bytes = db.to_bytes();
+        let read = OpeningDatabase::from_bytes(&bytes).unwrap();
+        assert_eq!(read.to_bytes(), bytes);
+        assert_eq!(read.stats(&line(&["e2e4"])), db.stats(&line(&["e2e4"])));
+        let fen = Board::from_str("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        for board in [Board::default(), fen] {
+            assert_eq!(read.moves(&board), db.moves(&board));
+        }
+
+        let path = std::env::temp_dir().join("binary_books_test.nsbb");
+        db.save(&path).unwrap();
+        let loaded = OpeningDatabase::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.to_bytes(), bytes);
+
+        let error = |bytes: &[u8]| {
+            OpeningDatabase::from_bytes(bytes)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(error(b"[Event"), "not a book file");
+        let mut newer = bytes.clone();
+        newer[5] = 9;
+        assert_eq!(error(&newer), "book file version 9, expected 2");
+        for len in 0..bytes.len() {
+            assert!(OpeningDatabase::from_bytes(&bytes[..len]).is_err(), "{len}");
+        }
+        // the first move of the trie made into a pawn promoting to a king
+        let mut bad_move = bytes.clone();
+        bad_move[6 + 1 + MoveStats::SIZE + 2] = 0x50;
+        assert_eq!(
+            error(&bad_move),
+            format!(
+                "corrupt book file: invalid item at byte {}",
+                6 + 1 + MoveStats::SIZE + 2
+            )
+        );
+    }
+
+    #[test]
+    fn hostile_books() {
+        let book = |moves: &[ChessMove]| {
+            let mut db = OpeningDatabase::new();
+            db.opening_lines.insert(moves);
+            db.to_bytes()
+        };
+        let e4 = line(&["e2e4"])[0];
+        // far too deep to be a game, and dropping it must not overflow the stack
+        let deep = book(&vec![e4; 300_000]);
+        assert!(matches!(
+            OpeningDatabase::from_bytes(&deep),
+            Err(BookError::Corrupt(TrieError::TooDeep { .. }))
+        ));
+        assert_eq!(
+            OpeningDatabase::from_bytes(&book(&[e4, e4]))
+                .err()
+                .unwrap()
+                .to_string(),
+            "corrupt book file: illegal move e2e4 in \
+             rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn learning_from_games() {
+        let mut db =
+            OpeningDatabase::from_pgn_reader(COLLECTION.as_bytes(), &BookOptions::default())
+                .unwrap();
+        // the game leaves the book with 3... Nf6 and white stands badly
+        let game = line(&["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "f3g5"]);
+        let learned = db.learn(Board::default(), &game, GameResult::BlackWins, Some(-300));
+        assert_eq!(learned, 5);
+
+        let e4 = db.stats(&game[..1]).unwrap();
+        assert_eq!((e4.games, e4.losses, e4.exit_eval()), (4, 2, Some(-300.0)));
+        assert!(e4.is_bad());
+        let e5 = db.stats(&game[..2]).unwrap();
+        assert_eq!((e5.wins, e5.exit_eval()), (2, Some(300.0)));
+        // the positions learn as well, whatever the move order
+        let after_nc6 = game[..4]
+            .iter()
+            .fold(Board::default(), |board, mov| board.make_move_new(*mov));
+        let bc4 = db
+            .moves(&after_nc6)
+            .into_iter()
+            .find(|(mov, _)| *mov == game[4]);
+        assert!(bc4.unwrap().1.is_bad());
+
+        // a bad move is avoided while there is another, played when there is not
+        let mut rng = StdRng::seed_from_u64(47);
+        for _ in 0..50 {
+            let mov = BookPolicy::Uniform.choose(&db.moves(&after_nc6), &mut rng);
+            assert_eq!(mov, Some(line(&["f1b5"])[0]));
+        }
+        let start = db.moves(&Board::default());
+        assert_eq!(BookPolicy::Best.choose(&start, &mut rng), Some(game[0]));
+
+        // the learned statistics survive saving the book
+        let read = OpeningDatabase::from_bytes(&db.to_bytes()).unwrap();
+        assert_eq!(read.stats(&game[..1]), Some(e4));
+
+        // a game that never was in the book teaches nothing
+        let d4 = line(&["d2d4"]);
+        assert_eq!(db.learn(Board::default(), &d4, GameResult::Draw, None), 0);
+    }
+
+    #[test]
+    fn book_policies() {
+        let stats = |games, wins, draws, losses| MoveStats {
+            games,
+            wins,
+            draws,
+            losses,
+            ..Default::default()
+        };
+        let [e4, d4, c4, nf3] = ["e2e4", "d2d4", "c2c4", "g1f3"].map(|m| line(&[m])[0]);
+        let moves = [
+            (e4, stats(60, 30, 20, 10)),
+            (d4, stats(30, 10, 10, 10)),
+            (c4, stats(9, 0, 0, 9)),
+            // won its only game, which says little
+            (nf3, stats(1, 1, 0, 0)),
+        ];
+        let mut rng = StdRng::seed_from_u64(45);
+        assert_eq!(BookPolicy::Best.choose(&moves, &mut rng), Some(e4));
+        assert_eq!(BookPolicy::Best.choose(&[], &mut rng), None);
+
+        let count = |policy: BookPolicy, rng: &mut StdRng| {
+            let mut counts = HashMap::new();
+            for _ in 0..1000 {
+                *counts
+                    .entry(policy.choose(&moves, rng).unwrap())
+                    .or_insert(0) += 1;
+            }
+            counts
+        };
+        let frequency = count(BookPolicy::Frequency, &mut rng);
+        assert!((550..700).contains(&frequency[&e4]), "{frequency:?}");
+        assert!(frequency[&c4] > 0);
+        // a move that never scored is never picked by score
+        let score = count(BookPolicy::Score, &mut rng);
+        assert!(!score.contains_key(&c4), "{score:?}");
+        let uniform = count(BookPolicy::Uniform, &mut rng);
+        assert!(
+            uniform.values().all(|n| (180..320).contains(n)),
+            "{uniform:?}"
+        );
+
+        // the same seed plays the same moves
+        let sequence = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..20)
+                .map(|_| BookPolicy::Frequency.choose(&moves, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sequence(7), sequence(7));
+
+        let weighted = [(e4, 0), (d4, 0)];
+        assert_eq!(
+            BookPolicy::Best.choose_weighted(&[(e4, 3), (d4, 5)], &mut rng),
+            Some(d4)
+        );
+        assert!(BookPolicy::Score
+            .choose_weighted(&weighted, &mut rng)
+            .is_some());
+        assert_eq!("Uniform".parse::<BookPolicy>(), Ok(BookPolicy::Uniform));
+    }
+
+    #[test]
+    fn header_filters() {
+        let count = |options: BookOptions| {
+            let db = OpeningDatabase::from_pgn_reader(COLLECTION.as_bytes(), &options).unwrap();
+            db.stats(&line(&["e2e4"])).map_or(0, |stats| stats.games)
+        };
+        assert_eq!(count(BookOptions::default().with_min_elo(2400)), 2);
+        assert_eq!(
+            count(BookOptions::default().with_results(&[GameResult::WhiteWins])),
+            1
+        );
+        assert_eq!(count(BookOptions::default().with_eco(&["C"])), 2);
+        assert_eq!(count(BookOptions::default().with_eco(&["C6", "B2"])), 2);
+        assert_eq!(
+            count(BookOptions::default().with_min_elo(2400).with_eco(&["B"])),
+            1
+        );
     }
 }
@@ -0,0 +1,787 @@
+//! Games in Portable Game Notation: the tag pairs and the movetext with its comments, NAGs and
+//! variations, read into a tree of moves.
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    io::{self, BufRead},
+    str::FromStr,
+};
+
+use chess::{Board, ChessMove};
+
+#[derive(Debug)]
+pub enum PgnError {
+    Io(io::Error),
+    UnexpectedChar { line: usize, found: char },
+    UnterminatedComment { line: usize },
+    BadTag { line: usize },
+    UnexpectedToken { line: usize, token: String },
+    UnclosedVariation { line: usize },
+    IllegalMove { line: usize, san: String },
+    BadFen(String),
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::Io(err) => write!(f, "could not read PGN: {err}"),
+            PgnError::UnexpectedChar { line, found } => {
+                write!(f, "line {line}: unexpected character `{found}`")
+            }
+            PgnError::UnterminatedComment { line } => {
+                write!(f, "line {line}: comment is never closed")
+            }
+            PgnError::BadTag { line } => write!(f, "line {line}: malformed tag pair"),
+            PgnError::UnexpectedToken { line, token } => {
+                write!(f, "line {line}: `{token}` is not allowed here")
+            }
+            PgnError::UnclosedVariation { line } => {
+                write!(f, "line {line}: variation is never closed")
+            }
+            PgnError::IllegalMove { line, san } => {
+                write!(f, "line {line}: `{san}` is not a legal move")
+            }
+            PgnError::BadFen(fen) => write!(f, "`{fen}` in the FEN tag is not a position"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl From<io::Error> for PgnError {
+    fn from(value: io::Error) -> Self {
+        PgnError::Io(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// `*`, the game is unfinished or the result is not known
+    #[default]
+    Unknown,
+}
+
+impl GameResult {
+    pub(crate) fn parse(token: &str) -> Option<Self> {
+        match token {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None,
+        }
+    }
+}
+
+impl Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let token = match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        };
+        write!(f, "{token}")
+    }
+}
+
+/// a move of the game with what is annotated after it and the lines played instead of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnMove {
+    pub mov: ChessMove,
+    pub san: String,
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    /// alternatives to this move, each starting from the position before it
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PgnGame {
+    /// tag pairs in the order they were given
+    pub headers: Vec<(String, String)>,
+    /// the comment in front of the first move
+    pub comment: Option<String>,
+    pub moves: Vec<PgnMove>,
+    pub result: GameResult,
+}
+
+impl PgnGame {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// the position of the `FEN` tag, the normal start position without one
+    pub fn start_board(&self) -> Result<Board, PgnError> {
+        match self.header("FEN") {
+            Some(fen) => Board::from_str(fen).map_err(|_| PgnError::BadFen(fen.to_string())),
+            None => Ok(Board::default()),
+        }
+    }
+
+    pub fn mainline(&self) -> Vec<ChessMove> {
+        self.moves.iter().map(|m| m.mov).collect()
+    }
+
+    /// every line from the start position to the end of the main line or of a variation
+    pub fn lines(&self) -> Vec<Vec<ChessMove>> {
+        fn collect(moves: &[PgnMove], prefix: &mut Vec<ChessMove>, out: &mut Vec<Vec<ChessMove>>) {
+            let start = prefix.len();
+            for mov in moves {
+                for variation in &mov.variations {
+                    let len = prefix.len();
+                    collect(variation, prefix, out);
+                    prefix.truncate(len);
+                }
+                prefix.push(mov.mov);
+            }
+            out.push(prefix.clone());
+            prefix.truncate(start);
+        }
+
+        let mut out = vec![];
+        collect(&self.moves, &mut vec![], &mut out);
+        out
+    }
+}
+
+impl FromStr for PgnGame {
+    type Err = PgnError;
+
+    /// the first game of `s`, an empty game if there is none
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Parser::new(s, 1).game()?.unwrap_or_default())
+    }
+}
+
+/// every game of a PGN collection
+pub fn parse_games(pgn: &str) -> Result<Vec<PgnGame>, PgnError> {
+    Parser::new(pgn, 1).games()
+}
+
+/// Reads the games of a collection one at a time so a large file never has to be in memory as a
+/// whole. Error lines count from the start of the input.
+pub struct PgnReader<R: BufRead> {
+    input: R,
+    /// line number of the next line to read
+    line: usize,
+    /// a tag line that was read while looking for the end of the previous game
+    pending: Option<String>,
+    queued: VecDeque<PgnGame>,
+    done: bool,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            line: 1,
+            pending: None,
+            queued: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        if let Some(line) = self.pending.take() {
+            return Ok(Some(line));
+        }
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        self.line += 1;
+        Ok(Some(line))
+    }
+
+    /// The text of the next game and the line it starts on. A game ends where a tag pair starts
+    /// a line after some movetext, outside of a brace comment.
+    fn next_text(&mut self) -> io::Result<Option<(String, usize)>> {
+        let mut text = String::new();
+        let mut first_line = self.line - usize::from(self.pending.is_some());
+        let mut in_movetext = false;
+        let mut in_comment = false;
+
+        while let Some(line) = self.read_line()? {
+            let trimmed = line.trim();
+            if text.trim().is_empty() && trimmed.is_empty() {
+                first_line = self.line;
+                continue;
+            }
+            let is_tag = !in_comment && trimmed.starts_with('[');
+            if in_movetext && is_tag {
+                self.pending = Some(line);
+                break;
+            }
+            let is_escape = !in_comment && trimmed.starts_with('%');
+            if !is_tag && !is_escape && !trimmed.is_empty() {
+                in_movetext = true;
+                for c in trimmed.chars() {
+                    match c {
+                        '{' if !in_comment => in_comment = true,
+                        '}' if in_comment => in_comment = false,
+                        ';' if !in_comment => break,
+                        _ => {}
+                    }
+                }
+            }
+            text.push_str(&line);
+        }
+
+        Ok((!text.trim().is_empty()).then_some((text, first_line)))
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(game) = self.queued.pop_front() {
+            return Some(Ok(game));
+        }
+        if self.done {
+            return None;
+        }
+
+        // movetext with results but no tags between the games still holds several of them
+        let games = self.next_text().map_err(PgnError::from).and_then(|text| {
+            let Some((text, line)) = text else {
+                return Ok(vec![]);
+            };
+            Parser::new(&text, line).games()
+        });
+        match games {
+            Ok(games) if games.is_empty() => {
+                self.done = true;
+                None
+            }
+            Ok(games) => {
+                self.queued.extend(games);
+                self.queued.pop_front().map(Ok)
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Tag(String, String),
+    MoveNumber,
+    San(String),
+    Nag(u8),
+    Comment(String),
+    VariationStart,
+    VariationEnd,
+    Result(GameResult),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Tag(name, value) => write!(f, "[{name} \"{value}\"]"),
+            Token::MoveNumber => write!(f, "move number"),
+            Token::San(san) => write!(f, "{san}"),
+            Token::Nag(nag) => write!(f, "${nag}"),
+            Token::Comment(comment) => write!(f, "{{{comment}}}"),
+            Token::VariationStart => write!(f, "("),
+            Token::VariationEnd => write!(f, ")"),
+            Token::Result(result) => write!(f, "{result}"),
+        }
+    }
+}
+
+/// the NAG a suffix annotation like `!?` stands for
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+struct Lexer {
+    input: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl Lexer {
+    fn new(input: &str, line: usize) -> Self {
+        Self {
+            input: input.chars().collect(),
+            position: 0,
+            line,
+        }
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.input.get(self.position).copied()
+    }
+
+    fn read_char(&mut self) -> Option<char> {
+        let c = self.peek_char()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn read_while(&mut self, keep: impl Fn(char) -> bool) -> String {
+        let mut res = String::new();
+        while let Some(c) = self.peek_char().filter(|c| keep(*c)) {
+            res.push(c);
+            self.read_char();
+        }
+        res
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.position == 0 || self.input[self.position - 1] == '\n'
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            self.read_while(char::is_whitespace);
+            // `%` in the first column escapes the whole line
+            if self.peek_char() == Some('%') && self.at_line_start() {
+                self.read_while(|c| c != '\n');
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// the next token and the line it starts on
+    fn next_token(&mut self) -> Result<Option<(Token, usize)>, PgnError> {
+        self.skip_whitespace();
+        let line = self.line;
+        let Some(c) = self.read_char() else {
+            return Ok(None);
+        };
+
+        let token = match c {
+            '[' => self.tag(line)?,
+            '{' => {
+                let comment = self.read_while(|c| c != '}');
+                if self.read_char().is_none() {
+                    return Err(PgnError::UnterminatedComment { line });
+                }
+                Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" "))
+            }
+            ';' => Token::Comment(self.read_while(|c| c != '\n').trim().to_string()),
+            '(' => Token::VariationStart,
+            ')' => Token::VariationEnd,
+            '*' => Token::Result(GameResult::Unknown),
+            '$' => {
+                let digits = self.read_while(|c| c.is_ascii_digit());
+                let nag = digits
+                    .parse()
+                    .map_err(|_| PgnError::UnexpectedChar { line, found: '$' })?;
+                Token::Nag(nag)
+            }
+            '!' | '?' => {
+                let suffix = format!("{c}{}", self.read_while(|c| c == '!' || c == '?'));
+                let nag = suffix_nag(&suffix).ok_or(PgnError::UnexpectedChar { line, found: c })?;
+                Token::Nag(nag)
+            }
+            c if c.is_ascii_digit() => {
+                let mut word = format!("{c}{}", self.read_while(|c| c.is_ascii_digit()));
+                if self.peek_char() == Some('.') {
+                    self.read_while(|c| c == '.');
+                    Token::MoveNumber
+                } else {
+                    word.push_str(&self.read_while(is_symbol));
+                    match GameResult::parse(&word) {
+                        Some(result) => Token::Result(result),
+                        None => Token::San(word),
+                    }
+                }
+            }
+            c if c.is_ascii_alphabetic() => {
+                let word = format!("{c}{}", self.read_while(is_symbol));
+                Token::San(word)
+            }
+            found => return Err(PgnError::UnexpectedChar { line, found }),
+        };
+        Ok(Some((token, line)))
+    }
+
+    /// `[Name "value"]` after the opening bracket
+    fn tag(&mut self, line: usize) -> Result<Token, PgnError> {
+        self.skip_whitespace();
+        let name = self.read_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        self.skip_whitespace();
+        if name.is_empty() || self.read_char() != Some('"') {
+            return Err(PgnError::BadTag { line });
+        }
+
+        let mut value = String::new();
+        loop {
+            match self.read_char() {
+                Some('\\') => match self.read_char() {
+                    Some(escaped) => value.push(escaped),
+                    None => return Err(PgnError::BadTag { line }),
+                },
+                Some('"') => break,
+                Some('\n') | None => return Err(PgnError::BadTag { line }),
+                Some(c) => value.push(c),
+            }
+        }
+
+        self.skip_whitespace();
+        if self.read_char() != Some(']') {
+            return Err(PgnError::BadTag { line });
+        }
+        Ok(Token::Tag(name, value))
+    }
+}
+
+/// characters that continue a move or a result token
+fn is_symbol(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '+' | '#' | '=' | '-' | '/' | ':' | '_')
+}
+
+/// The move `san` stands for. The chess crate wants the promotion piece without `=`, castling
+/// with letters and no check marks.
+fn resolve_san(board: &Board, san: &str) -> Option<ChessMove> {
+    let san = san
+        .trim_end_matches(['+', '#'])
+        .replace('=', "")
+        .replace('0', "O");
+    ChessMove::from_san(board, &san).ok()
+}
+
+struct Parser {
+    lexer: Lexer,
+    peeked: Option<(Token, usize)>,
+}
+
+impl Parser {
+    /// `line` is the line number `input` starts on
+    fn new(input: &str, line: usize) -> Self {
+        Self {
+            lexer: Lexer::new(input, line),
+            peeked: None,
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token>, PgnError> {
+        if self.peeked.is_none() {
+            self.peeked = self.lexer.next_token()?;
+        }
+        Ok(self.peeked.as_ref().map(|(token, _)| token))
+    }
+
+    fn next(&mut self) -> Result<Option<(Token, usize)>, PgnError> {
+        match self.peeked.take() {
+            Some(token) => Ok(Some(token)),
+            None => self.lexer.next_token(),
+        }
+    }
+
+    fn games(&mut self) -> Result<Vec<PgnGame>, PgnError> {
+        let mut games = vec![];
+        while let Some(game) = self.game()? {
+            games.push(game);
+        }
+        Ok(games)
+    }
+
+    /// the next game, `None` once the input is used up
+    fn game(&mut self) -> Result<Option<PgnGame>, PgnError> {
+        let mut game = PgnGame::default();
+        while let Some(Token::Tag(..)) = self.peek()? {
+            if let Some((Token::Tag(name, value), _)) = self.next()? {
+                game.headers.push((name, value));
+            }
+        }
+        if game.headers.is_empty() && self.peek()?.is_none() {
+            return Ok(None);
+        }
+
+        game.result = game
+            .header("Result")
+            .and_then(GameResult::parse)
+            .unwrap_or_default();
+        let board = game.start_board()?;
+        let (comment, moves, result) = self.line(board, 0)?;
+        game.comment = comment;
+        game.moves = moves;
+        if let Some(result) = result {
+            game.result = result;
+        }
+        Ok(Some(game))
+    }
+
+    /// Moves from `board` until the end of the variation, or of the game at `depth` 0. Returns
+    /// the comment in front of the first move and the result token if the game ended with one.
+    #[allow(clippy::type_complexity)]
+    fn line(
+        &mut self,
+        mut board: Board,
+        depth: usize,
+    ) -> Result<(Option<String>, Vec<PgnMove>, Option<GameResult>), PgnError> {
+        let mut leading_comment: Option<String> = None;
+        let mut moves: Vec<PgnMove> = vec![];
+        // variations replace the last move, so they start from the position before it
+        let mut before = board;
+        let start_line = self.lexer.line;
+
+        loop {
+            // a tag pair after the movetext starts the next game of a collection without results
+            if depth == 0 && matches!(self.peek()?, Some(Token::Tag(..))) {
+                return Ok((leading_comment, moves, None));
+            }
+            let Some((token, line)) = self.next()? else {
+                if depth > 0 {
+                    return Err(PgnError::UnclosedVariation { line: start_line });
+                }
+                return Ok((leading_comment, moves, None));
+            };
+
+            match token {
+                Token::MoveNumber => {}
+                Token::San(san) => {
+                    let mov = resolve_san(&board, &san).ok_or(PgnError::IllegalMove {
+                        line,
+                        san: san.clone(),
+                    })?;
+                    before = board;
+                    board = board.make_move_new(mov);
+                    moves.push(PgnMove {
+                        mov,
+                        san,
+                        nags: vec![],
+                        comment: None,
+                        variations: vec![],
+                    });
+                }
+                // an annotation in front of any move has nothing to refer to
+                Token::Nag(nag) => {
+                    if let Some(last) = moves.last_mut() {
+                        last.nags.push(nag);
+                    }
+                }
+                Token::Comment(comment) => {
+                    let target = match moves.last_mut() {
+                        Some(last) => &mut last.comment,
+                        None => &mut leading_comment,
+                    };
+                    match target {
+                        Some(existing) => {
+                            existing.push(' ');
+                            existing.push_str(&comment);
+                        }
+                        None => *target = Some(comment),
+                    }
+                }
+                Token::VariationStart => {
+                    if moves.is_empty() {
+                        return Err(PgnError::UnexpectedToken {
+                            line,
+                            token: token.to_string(),
+                        });
+                    }
+                    // a comment opening a variation is dropped, there is no move to hold it
+                    let (_, variation, _) = self.line(before, depth + 1)?;
+                    if let Some(last) = moves.last_mut() {
+                        last.variations.push(variation);
+                    }
+                }
+                Token::VariationEnd if depth > 0 => return Ok((leading_comment, moves, None)),
+                Token::Result(result) if depth == 0 => {
+                    return Ok((leading_comment, moves, Some(result)))
+                }
+                token => {
+                    return Err(PgnError::UnexpectedToken {
+                        line,
+                        token: token.to_string(),
+                    })
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::line;
+
+    #[test]
+    fn headers_and_result() {
+        let pgn = r#"[Event "Casual \"blitz\""]
+[White "A"]
+[Black "B"]
+[Result "0-1"]
+
+1. e4 e5 2. Nf3 1-0
+"#;
+        let game = PgnGame::from_str(pgn).unwrap();
+        assert_eq!(game.header("Event"), Some("Casual \"blitz\""));
+        assert_eq!(game.header("Black"), Some("B"));
+        assert_eq!(game.header("ECO"), None);
+        // the result token wins over the tag
+        assert_eq!(game.result, GameResult::WhiteWins);
+        // an odd number of moves is fine
+        assert_eq!(game.mainline(), line(&["e2e4", "e7e5", "g1f3"]));
+    }
+
+    #[test]
+    fn annotations() {
+        let pgn = "{Start} 1.e4!? $14 {best by test} 1...c5 ; Sicilian\n\
+                   2.Nf3 d6?! 3.d4 cxd4 4.Nxd4 Nf6 5.Nc3 a6 6.Bg5 e6 7.f4 Qb6 8.Qd2 Qxb2 \
+                   9.Rb1 Qa3 10.f5 Nc6 11.fxe6 fxe6 12.Nxc6 bxc6 13.e5 dxe5 14.Bxf6 gxf6 \
+                   15.Ne4 Be7 16.Be2 h5 17.Rb3 Qa4 18.Nxf6+ Bxf6 19.c4 Bh4+ *";
+        let game = PgnGame::from_str(pgn).unwrap();
+        assert_eq!(game.comment.as_deref(), Some("Start"));
+        assert_eq!(game.moves[0].nags, vec![5, 14]);
+        assert_eq!(game.moves[0].comment.as_deref(), Some("best by test"));
+        assert_eq!(game.moves[1].comment.as_deref(), Some("Sicilian"));
+        assert_eq!(game.moves[3].nags, vec![6]);
+        assert_eq!(game.moves.len(), 38);
+        assert_eq!(game.result, GameResult::Unknown);
+    }
+
+    #[test]
+    fn variations() {
+        let pgn = "1. e4 (1. d4 d5 (1... Nf6 2. c4) 2. c4) 1... e5 (1... c5) 2. Nf3 1/2-1/2";
+        let game = PgnGame::from_str(pgn).unwrap();
+        assert_eq!(game.mainline(), line(&["e2e4", "e7e5", "g1f3"]));
+        assert_eq!(game.moves[0].variations.len(), 1);
+        assert_eq!(game.moves[0].variations[0][1].variations.len(), 1);
+        assert_eq!(game.result, GameResult::Draw);
+
+        assert_eq!(
+            game.lines(),
+            vec![
+                line(&["d2d4", "g8f6", "c2c4"]),
+                line(&["d2d4", "d7d5", "c2c4"]),
+                line(&["e2e4", "c7c5"]),
+                line(&["e2e4", "e7e5", "g1f3"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn special_moves() {
+        let pgn = r#"[FEN "r3k3/8/8/8/8/8/1p6/R3K2R w KQq - 0 1"]
+
+1. O-O O-O-O 2. Ra8+ Kd7 3. Rb8 b1=Q 4. Rfxb1 *"#;
+        let game = PgnGame::from_str(pgn).unwrap();
+        assert_eq!(
+            game.mainline(),
+            line(&["e1g1", "e8c8", "a1a8", "c8d7", "a8b8", "b2b1q", "f1b1"])
+        );
+
+        let game = PgnGame::from_str("1. e4 e5 2. Nf3 Nf6 3. Bc4 Bc5 4. 0-0 *").unwrap();
+        assert_eq!(game.mainline()[6], ChessMove::from_str("e1g1").unwrap());
+    }
+
+    #[test]
+    fn collections() {
+        let pgn = "[Event \"1\"]\n\n1. e4 e5 1-0\n\n\
+                   % a comment line\n\
+                   [Event \"2\"]\n\n1. d4 *\n\n\
+                   [Event \"3\"]\n\n1. c4\n\n\
+                   [Event \"4\"]\n\n1. Nf3\n";
+        let games = parse_games(pgn).unwrap();
+        assert_eq!(games.len(), 4);
+        assert_eq!(games[1].header("Event"), Some("2"));
+        assert_eq!(games[2].mainline(), line(&["c2c4"]));
+        assert_eq!(games[3].mainline(), line(&["g1f3"]));
+        assert!(parse_games("  \n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_games_one_at_a_time() {
+        let pgn = "\n[Event \"1\"]\n[Result \"1-0\"]\n\n1. e4 {a comment\n[spanning lines]} e5\n\
+                   1-0\n\n[Event \"2\"]\n\n1. d4 d5 * 1. c4 *\n\n\
+                   [Event \"3\"]\n\n1. Nf3 Nf6 2. Ke3\n\n\
+                   [Event \"4\"]\n\n1. b3 *\n";
+        let mut reader = PgnReader::new(pgn.as_bytes());
+
+        let game = reader.next().unwrap().unwrap();
+        assert_eq!(game.header("Event"), Some("1"));
+        assert_eq!(
+            game.moves[0].comment.as_deref(),
+            Some("a comment [spanning lines]")
+        );
+        assert_eq!(game.mainline(), line(&["e2e4", "e7e5"]));
+        let game = reader.next().unwrap().unwrap();
+        assert_eq!(game.mainline(), line(&["d2d4", "d7d5"]));
+        let game = reader.next().unwrap().unwrap();
+        assert_eq!(game.mainline(), line(&["c2c4"]));
+        // the line counts from the start of the whole input
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "line 15: `Ke3` is not a legal move");
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn errors() {
+        let table = [
+            (
+                "1. e4 e5\n2. Nf6",
+                PgnError::IllegalMove {
+                    line: 2,
+                    san: "Nf6".into(),
+                },
+            ),
+            (
+                "1. e4 {never closed",
+                PgnError::UnterminatedComment { line: 1 },
+            ),
+            ("[Event \"x]\n1. e4", PgnError::BadTag { line: 1 }),
+            (
+                "1. e4 (1. d4 d5\n2. c4",
+                PgnError::UnclosedVariation { line: 1 },
+            ),
+            (
+                "1. e4 e5)",
+                PgnError::UnexpectedToken {
+                    line: 1,
+                    token: ")".into(),
+                },
+            ),
+            (
+                "(1. e4) *",
+                PgnError::UnexpectedToken {
+                    line: 1,
+                    token: "(".into(),
+                },
+            ),
+            (
+                "1. e4 & e5",
+                PgnError::UnexpectedChar {
+                    line: 1,
+                    found: '&',
+                },
+            ),
+            (
+                "[FEN \"nonsense\"]\n1. e4",
+                PgnError::BadFen("nonsense".into()),
+            ),
+        ];
+        for (pgn, expected) in table {
+            let err = PgnGame::from_str(pgn).unwrap_err();
+            assert_eq!(err.to_string(), expected.to_string(), "{pgn}");
+        }
+    }
+}
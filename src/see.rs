@@ -0,0 +1,218 @@
+//! Static exchange evaluation: the material balance of the captures on one square, each side
+//! always taking with its cheapest piece and free to stop when going on would lose more.
+
+use chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, BitBoard,
+    Board, ChessMove, Color, Piece, Square, ALL_PIECES, EMPTY,
+};
+
+/// piece values of the exchange, the king is never actually traded
+pub const SEE_VALUES: [isize; 6] = [100, 300, 300, 500, 900, 20_000];
+
+fn value(piece: Piece) -> isize {
+    SEE_VALUES[piece.to_index()]
+}
+
+/// every piece of either colour that attacks `sq` with `occupied` as the blockers
+pub fn attackers_to(board: &Board, sq: Square, occupied: BitBoard) -> BitBoard {
+    let white_pawns = board.pieces(Piece::Pawn) & board.color_combined(Color::White);
+    let black_pawns = board.pieces(Piece::Pawn) & board.color_combined(Color::Black);
+    let diagonal = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+    let straight = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+
+    (get_pawn_attacks(sq, Color::Black, white_pawns)
+        | get_pawn_attacks(sq, Color::White, black_pawns)
+        | (get_knight_moves(sq) & board.pieces(Piece::Knight))
+        | (get_king_moves(sq) & board.pieces(Piece::King))
+        | (get_bishop_moves(sq, occupied) & diagonal)
+        | (get_rook_moves(sq, occupied) & straight))
+        & occupied
+}
+
+fn least_valuable(board: &Board, attackers: BitBoard) -> Option<(Square, Piece)> {
+    ALL_PIECES.iter().find_map(|piece| {
+        let own = attackers & board.pieces(*piece);
+        (own != EMPTY).then(|| (own.to_square(), *piece))
+    })
+}
+
+/// Resolves the exchange on `to` after the first capture: `gain` is what that capture won,
+/// `on_square` the value of the piece now standing on `to` and `side` the colour to recapture.
+fn exchange(
+    board: &Board,
+    to: Square,
+    mut occupied: BitBoard,
+    mut side: Color,
+    first_gain: isize,
+    mut on_square: isize,
+) -> isize {
+    let mut gain = vec![first_gain];
+    let mut attackers = attackers_to(board, to, occupied);
+
+    loop {
+        let own = attackers & board.color_combined(side);
+        let Some((from, piece)) = least_valuable(board, own) else {
+            break;
+        };
+        // the king cannot take a piece that is still defended
+        let defenders = attackers & board.color_combined(!side) & !BitBoard::from_square(from);
+        if piece == Piece::King && defenders != EMPTY {
+            break;
+        }
+
+        let last = *gain.last().expect("starts with the first capture");
+        gain.push(on_square - last);
+
+        on_square = value(piece);
+        occupied ^= BitBoard::from_square(from);
+        // sliders behind the piece that just moved now see the square
+        attackers = attackers_to(board, to, occupied);
+        side = !side;
+    }
+
+    while gain.len() > 1 {
+        let last = gain.pop().expect("more than one entry");
+        let prev = gain.last_mut().expect("more than one entry");
+        *prev = -(-*prev).max(last);
+    }
+    gain[0]
+}
+
+/// the material `mov` wins, or loses when negative, once the exchange on its destination is
+/// played out
+pub fn see(board: &Board, mov: ChessMove) -> isize {
+    let from = mov.get_source();
+    let to = mov.get_dest();
+    let Some(piece) = board.piece_on(from) else {
+        return 0;
+    };
+    let side = board.side_to_move();
+    let mut occupied = *board.combined() ^ BitBoard::from_square(from);
+
+    let mut gain = match board.piece_on(to) {
+        Some(captured) => value(captured),
+        None if piece == Piece::Pawn && from.get_file() != to.get_file() => {
+            // en passant, the pawn taken is beside the destination
+            let taken = Square::make_square(from.get_rank(), to.get_file());
+            occupied ^= BitBoard::from_square(taken);
+            value(Piece::Pawn)
+        }
+        None => 0,
+    };
+    let mut on_square = value(piece);
+    if let Some(promotion) = mov.get_promotion() {
+        gain += value(promotion) - value(Piece::Pawn);
+        on_square = value(promotion);
+    }
+
+    exchange(
+        board,
+        to,
+        occupied | BitBoard::from_square(to),
+        !side,
+        gain,
+        on_square,
+    )
+}
+
+/// whether `mov` wins at least `threshold` in the exchange
+pub fn see_ge(board: &Board, mov: ChessMove, threshold: isize) -> bool {
+    see(board, mov) >= threshold
+}
+
+/// What `side` wins by starting the exchange on the piece at `sq` with its cheapest attacker, 0
+/// if it cannot or should not capture. It does not matter whose move it is.
+pub fn see_square(board: &Board, sq: Square, side: Color) -> isize {
+    let Some(target) = board.piece_on(sq) else {
+        return 0;
+    };
+    let occupied = *board.combined();
+    let own = attackers_to(board, sq, occupied) & board.color_combined(side);
+    let Some((from, piece)) = least_valuable(board, own) else {
+        return 0;
+    };
+    if piece == Piece::King
+        && attackers_to(board, sq, occupied) & board.color_combined(!side) != EMPTY
+    {
+        return 0;
+    }
+
+    let won = exchange(
+        board,
+        sq,
+        occupied ^ BitBoard::from_square(from),
+        !side,
+        value(target),
+        value(piece),
+    );
+    won.max(0)
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chess::{Board, ChessMove, Color, Square};
+
+    use super::*;
+
+    #[test]
+    fn known_exchanges() {
+        let table = [
+            // undefended pawn
+            (
+                "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1",
+                "e1e5",
+                100,
+            ),
+            // knight takes a pawn and the whole e-file battery joins in
+            (
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "d3e5",
+                -200,
+            ),
+            ("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5", 100),
+            // the queen takes a defended pawn
+            ("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1", "d1d5", -800),
+            // the rook behind the first one decides the exchange
+            ("3rk3/8/8/3p4/8/8/3R4/3R2K1 w - - 0 1", "d2d5", 100),
+            ("3rk3/8/8/3p4/8/8/3R4/6K1 w - - 0 1", "d2d5", -400),
+            // en passant
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 100),
+            // promotion without a capture
+            ("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q", 800),
+            // the king may only recapture an undefended piece
+            ("8/8/4k3/3p4/8/8/8/3RK3 w - - 0 1", "d1d5", -400),
+            ("8/8/4k3/3p4/8/1B6/8/3RK3 w - - 0 1", "d1d5", 100),
+            // quiet move onto an attacked square
+            ("4k3/8/8/8/8/2p5/8/3NK3 w - - 0 1", "d1b2", -300),
+            ("4k3/8/8/8/8/8/8/3NK3 w - - 0 1", "d1c3", 0),
+        ];
+
+        for (fen, mov, expected) in table {
+            let board = Board::from_str(fen).unwrap();
+            let mov = ChessMove::from_str(mov).unwrap();
+            assert_eq!(see(&board, mov), expected, "{fen} {mov}");
+        }
+    }
+
+    #[test]
+    fn threshold() {
+        let board = Board::from_str("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        let mov = ChessMove::from_str("d1d5").unwrap();
+        assert!(see_ge(&board, mov, -800));
+        assert!(!see_ge(&board, mov, 0));
+    }
+
+    #[test]
+    fn square_exchanges() {
+        let board = Board::from_str("8/8/4k3/3p4/8/1B6/8/3RK3 b - - 0 1").unwrap();
+        let d5 = Square::from_str("d5").unwrap();
+        assert_eq!(see_square(&board, d5, Color::White), 100);
+        // black has nothing that attacks its own pawn's square
+        assert_eq!(see_square(&board, d5, Color::Black), 0);
+
+        let board = Board::from_str("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        assert_eq!(see_square(&board, d5, Color::White), 0);
+    }
+}
@@ -0,0 +1,1286 @@
+//! Syzygy endgame tablebase probing.
+//!
+//! A port of the probing code used by Stockfish and Fathom. Tables are found by scanning the
+//! `SyzygyPath` directories for `.rtbw` (win/draw/loss) and `.rtbz` (distance to zeroing) files,
+//! each file is read into memory the first time a position needs it.
+//!
+//! Positions are looked up by their material, e.g. `KRPvKR`. A table only stores the positions
+//! with the stronger side as white, the other colour is handled by flipping the board. The
+//! index of a position is built from groups of pieces (the leading pawns or the kings, then the
+//! remaining pawns, then every other kind of piece) and the value is found in blocks of Huffman
+//! coded "recursive pairing" symbols.
+
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+use chess::{
+    get_king_moves, BitBoard, Board, BoardStatus, CastleRights, ChessMove, Color, File, MoveGen,
+    Piece, Rank, Square, EMPTY,
+};
+
+use crate::endgame::side_key;
+
+/// the biggest tables that exist
+pub const MAX_PIECES: usize = 7;
+/// what a tablebase win is worth to the search, above every known win of the evaluation
+pub const TB_WIN: isize = 20_000;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+const MAX_DTZ: i32 = 1 << 18;
+
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+/// the result of a position for the side to move; cursed wins and blessed losses are wins and
+/// losses that the 50-move rule turns into draws
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    fn from_i32(value: i32) -> Self {
+        match value {
+            i32::MIN..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    fn to_i32(self) -> i32 {
+        match self {
+            Wdl::Loss => -2,
+            Wdl::BlessedLoss => -1,
+            Wdl::Draw => 0,
+            Wdl::CursedWin => 1,
+            Wdl::Win => 2,
+        }
+    }
+
+    /// the value the search uses for this result, from the side to move's point of view
+    pub fn value(self) -> isize {
+        match self {
+            Wdl::Loss => -TB_WIN,
+            Wdl::BlessedLoss => -1,
+            Wdl::Draw => 0,
+            Wdl::CursedWin => 1,
+            Wdl::Win => TB_WIN,
+        }
+    }
+}
+
+impl std::ops::Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Self::Output {
+        Wdl::from_i32(-self.to_i32())
+    }
+}
+
+/// the squares and pieces are numbered the way the table files do: a1 = 0, white pawn = 1 up to
+/// white king = 6 and black pieces 8 higher
+fn piece_code(board: &Board, sq: Square) -> u8 {
+    let piece = board
+        .piece_on(sq)
+        .map_or(0, |piece| piece.to_index() as u8 + 1);
+    match board.color_on(sq) {
+        Some(Color::Black) => piece + 8,
+        _ => piece,
+    }
+}
+
+fn square(index: usize) -> Square {
+    Square::make_square(Rank::from_index(index / 8), File::from_index(index % 8))
+}
+
+fn off_a1h8(sq: usize) -> i32 {
+    (sq >> 3) as i32 - (sq & 7) as i32
+}
+
+fn rank_of(sq: usize) -> usize {
+    sq >> 3
+}
+
+fn file_of(sq: usize) -> usize {
+    sq & 7
+}
+
+/// lookup tables used to turn the squares of a position into an index
+struct Encoding {
+    map_pawns: [usize; 64],
+    map_b1h1h7: [usize; 64],
+    map_a1d1d4: [usize; 64],
+    map_kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; 6],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+impl Encoding {
+    fn new() -> Self {
+        let mut e = Encoding {
+            map_pawns: [0; 64],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        // squares below the a1-h8 diagonal to 0..27
+        let mut code = 0;
+        for sq in 0..64 {
+            if off_a1h8(sq) < 0 {
+                e.map_b1h1h7[sq] = code;
+                code += 1;
+            }
+        }
+
+        // the a1-d1-d4 triangle to 0..9, the diagonal squares last
+        let mut diagonal = Vec::new();
+        code = 0;
+        for sq in [0, 1, 2, 3, 8, 9, 10, 11, 16, 17, 18, 19, 24, 25, 26, 27] {
+            if off_a1h8(sq) < 0 {
+                e.map_a1d1d4[sq] = code;
+                code += 1;
+            } else if off_a1h8(sq) == 0 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            e.map_a1d1d4[sq] = code;
+            code += 1;
+        }
+
+        // the 462 legal ways to place two kings with the first one in the triangle, if it is on
+        // the diagonal the second one is not above it
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for idx in 0..10 {
+            // b1 is the square mapped to 0, the rest of the board is zero as well
+            for s1 in (0..28).filter(|&s1| e.map_a1d1d4[s1] == idx && (idx != 0 || s1 == 1)) {
+                let taken = get_king_moves(square(s1)) | BitBoard::from_square(square(s1));
+                for s2 in 0..64 {
+                    if taken & BitBoard::from_square(square(s2)) != EMPTY {
+                        continue;
+                    }
+                    if off_a1h8(s1) == 0 && off_a1h8(s2) > 0 {
+                        continue;
+                    }
+                    if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        e.map_kk[idx][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            e.map_kk[idx][s2] = code;
+            code += 1;
+        }
+
+        // ways to choose k of n squares
+        e.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..=n.min(5) {
+                let with = if k > 0 { e.binomial[k - 1][n - 1] } else { 0 };
+                let without = if k < n { e.binomial[k][n - 1] } else { 0 };
+                e.binomial[k][n] = with + without;
+            }
+        }
+
+        // a2-h7 to 47..0, the leading pawn is the one with the highest value: nearest to the
+        // edge and, on the same file, the lowest
+        let mut available = 47;
+        for lead_pawns in 1..=5 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..=6 {
+                    let sq = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        e.map_pawns[sq] = available;
+                        e.map_pawns[sq ^ 7] = available - 1;
+                        available = available.saturating_sub(2);
+                    }
+                    e.lead_pawn_idx[lead_pawns][sq] = idx;
+                    idx += e.binomial[lead_pawns - 1][e.map_pawns[sq]];
+                }
+                e.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+        e
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref ENCODING: Encoding = Encoding::new();
+}
+
+fn byte(bytes: &[u8], at: usize) -> u8 {
+    bytes.get(at).copied().unwrap_or(0)
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([byte(bytes, at), byte(bytes, at + 1)])
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([
+        byte(bytes, at),
+        byte(bytes, at + 1),
+        byte(bytes, at + 2),
+        byte(bytes, at + 3),
+    ])
+}
+
+fn u32_be(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([
+        byte(bytes, at),
+        byte(bytes, at + 1),
+        byte(bytes, at + 2),
+        byte(bytes, at + 3),
+    ])
+}
+
+fn u64_be(bytes: &[u8], at: usize) -> u64 {
+    (u32_be(bytes, at) as u64) << 32 | u32_be(bytes, at + 4) as u64
+}
+
+/// the material of a position with white first, e.g. `KRPvKR`
+pub fn material_code(board: &Board) -> String {
+    format!(
+        "{}v{}",
+        side_key(board, Color::White),
+        side_key(board, Color::Black)
+    )
+}
+
+/// what is known about a table from its name alone
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TableInfo {
+    /// the material with the stronger side as white, the name of the file
+    key: String,
+    /// the same material with the stronger side as black
+    key2: String,
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    /// pawns of the leading colour, then of the other one
+    pawn_count: [usize; 2],
+}
+
+impl TableInfo {
+    fn from_name(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let valid =
+            |side: &str| side.starts_with('K') && side[1..].chars().all(|c| "QRBNP".contains(c));
+        if !valid(white) || !valid(black) || white.len() + black.len() > MAX_PIECES {
+            return None;
+        }
+
+        let count = |side: &str, c: char| side.chars().filter(|p| *p == c).count();
+        let has_unique_pieces = [white, black]
+            .iter()
+            .any(|side| "QRBNP".chars().any(|c| count(side, c) == 1));
+
+        // the side with fewer pawns leads, it compresses better
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let pawn_count = if white_leads {
+            [white_pawns, black_pawns]
+        } else {
+            [black_pawns, white_pawns]
+        };
+
+        Some(Self {
+            key: format!("{white}v{black}"),
+            key2: format!("{black}v{white}"),
+            piece_count: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count,
+        })
+    }
+}
+
+/// how one part of a table (a side to move and a leading pawn file) is stored
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    flags: u8,
+    min_sym_len: u8,
+    block_size: usize,
+    span: usize,
+    num_blocks: usize,
+    /// offsets into the file
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    block_length_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    data: usize,
+    base64: Vec<u64>,
+    /// number of values a symbol expands to, minus one
+    symlen: Vec<u8>,
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+    /// offsets of the value maps of a DTZ table for wins, losses, cursed wins and blessed losses
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    fn left(&self, bytes: &[u8], sym: usize) -> usize {
+        let at = self.btree + 3 * sym;
+        ((byte(bytes, at + 1) as usize & 0xf) << 8) | byte(bytes, at) as usize
+    }
+
+    fn right(&self, bytes: &[u8], sym: usize) -> usize {
+        let at = self.btree + 3 * sym;
+        ((byte(bytes, at + 2) as usize) << 4) | (byte(bytes, at + 1) as usize >> 4)
+    }
+
+    fn symlen(&self, sym: usize) -> i64 {
+        self.symlen.get(sym).copied().unwrap_or(0) as i64
+    }
+
+    fn set_groups(&mut self, info: &TableInfo, order: [usize; 2], file: usize) {
+        let e = &*ENCODING;
+        let mut n = 0;
+        let mut first_len: i32 = if info.has_pawns {
+            0
+        } else if info.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        self.group_len[0] = 1;
+        for i in 1..info.piece_count {
+            first_len -= 1;
+            if first_len > 0 || self.pieces[i] == self.pieces[i - 1] {
+                self.group_len[n] += 1;
+            } else {
+                n += 1;
+                self.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        self.group_len[n] = 0;
+
+        // the groups are encoded in the order the file asks for, the leading group at order[0]
+        // and the remaining pawns at order[1]
+        let pp = info.has_pawns && info.pawn_count[1] > 0;
+        let mut next = if pp { 2 } else { 1 };
+        let mut free_squares = 64 - self.group_len[0] - if pp { self.group_len[1] } else { 0 };
+        let mut idx = 1u64;
+        let mut k = 0;
+        while (next < n || k == order[0] || k == order[1]) && k < 16 {
+            if k == order[0] {
+                self.group_idx[0] = idx;
+                idx *= if info.has_pawns {
+                    e.lead_pawns_size[self.group_len[0]][file]
+                } else if info.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                self.group_idx[1] = idx;
+                idx *= e.binomial[self.group_len[1]][48 - self.group_len[0]];
+            } else if next < n {
+                self.group_idx[next] = idx;
+                idx *= e.binomial[self.group_len[next]][free_squares];
+                free_squares -= self.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        self.group_idx[n] = idx;
+    }
+
+    /// reads the block and Huffman code layout, returns where the next record starts
+    fn set_sizes(&mut self, bytes: &[u8], mut at: usize) -> Option<usize> {
+        self.flags = byte(bytes, at);
+        at += 1;
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            // the single value is kept where the symbol length would be
+            self.min_sym_len = byte(bytes, at);
+            return Some(at + 1);
+        }
+
+        let groups = self.group_len.iter().position(|len| *len == 0)?;
+        let tb_size = self.group_idx[groups];
+
+        self.block_size = 1usize.checked_shl(byte(bytes, at) as u32)?;
+        self.span = 1usize.checked_shl(byte(bytes, at + 1) as u32)?;
+        self.sparse_index_size = (tb_size as usize).div_ceil(self.span);
+        let padding = byte(bytes, at + 2) as usize;
+        self.num_blocks = u32_le(bytes, at + 3) as usize;
+        self.block_length_size = self.num_blocks + padding;
+        let max_sym_len = byte(bytes, at + 7);
+        self.min_sym_len = byte(bytes, at + 8);
+        at += 9;
+        if max_sym_len < self.min_sym_len || self.min_sym_len == 0 {
+            return None;
+        }
+        self.lowest_sym = at;
+
+        // canonical Huffman code: longer symbols have lower values, base64[i] is the lowest
+        // symbol of length min_sym_len + i padded to 64 bits
+        let lengths = (max_sym_len - self.min_sym_len + 1) as usize;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = u16_le(bytes, self.lowest_sym + 2 * i) as u64;
+            let lowest_next = u16_le(bytes, self.lowest_sym + 2 * (i + 1)) as u64;
+            self.base64[i] = self.base64[i + 1]
+                .wrapping_add(lowest)
+                .wrapping_sub(lowest_next)
+                / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base
+                .checked_shl(64 - i as u32 - self.min_sym_len as u32)
+                .unwrap_or(0);
+        }
+        at += lengths * 2;
+
+        let symbols = u16_le(bytes, at) as usize;
+        at += 2;
+        self.btree = at;
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                self.symlen[sym] = self.set_symlen(bytes, sym, &mut visited);
+            }
+        }
+        Some(at + symbols * 3 + (symbols & 1))
+    }
+
+    /// every symbol is a pair of two others, a leaf stands for a single value
+    fn set_symlen(&mut self, bytes: &[u8], sym: usize, visited: &mut [bool]) -> u8 {
+        visited[sym] = true;
+        let right = self.right(bytes, sym);
+        if right == 0xfff {
+            return 0;
+        }
+        let left = self.left(bytes, sym);
+        if left >= visited.len() || right >= visited.len() {
+            return 0;
+        }
+        if !visited[left] {
+            self.symlen[left] = self.set_symlen(bytes, left, visited);
+        }
+        if !visited[right] {
+            self.symlen[right] = self.set_symlen(bytes, right, visited);
+        }
+        self.symlen[left]
+            .wrapping_add(self.symlen[right])
+            .wrapping_add(1)
+    }
+
+    /// the stored value at `idx`
+    fn decompress(&self, bytes: &[u8], idx: u64) -> i32 {
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            return self.min_sym_len as i32;
+        }
+
+        // the sparse index points into the block lengths every `span` values
+        let k = (idx / self.span as u64) as usize;
+        let entry = self.sparse_index + 6 * k;
+        let mut block = u32_le(bytes, entry) as i64;
+        let mut offset = u16_le(bytes, entry + 4) as i64;
+        offset += (idx % self.span as u64) as i64 - (self.span / 2) as i64;
+
+        let block_length =
+            |block: i64| u16_le(bytes, self.block_length + 2 * block.max(0) as usize) as i64;
+        while offset < 0 && block > 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) && (block as usize) < self.block_length_size {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        // walk the symbols of the block until the one that covers `offset`
+        let mut ptr = self.data + block as usize * self.block_size;
+        let mut buf64 = u64_be(bytes, ptr);
+        ptr += 8;
+        let mut buf64_size = 64;
+        let min_len = self.min_sym_len as u32;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while len + 1 < self.base64.len() && buf64 < self.base64[len] {
+                len += 1;
+            }
+            sym = (buf64.wrapping_sub(self.base64[len]))
+                .checked_shr(64 - len as u32 - min_len)
+                .unwrap_or(0) as u16;
+            sym = sym.wrapping_add(u16_le(bytes, self.lowest_sym + 2 * len));
+            let sym_values = self.symlen(sym as usize) + 1;
+            if offset < sym_values {
+                break;
+            }
+            offset -= sym_values;
+            let bits = len as u32 + min_len;
+            buf64 = buf64.checked_shl(bits).unwrap_or(0);
+            buf64_size -= bits as i32;
+            if buf64_size <= 32 {
+                buf64_size += 32;
+                buf64 |= (u32_be(bytes, ptr) as u64) << (64 - buf64_size);
+                ptr += 4;
+            }
+        }
+
+        // expand the pairs down to the single value
+        let mut sym = sym as usize;
+        while self.symlen(sym) != 0 {
+            let left = self.left(bytes, sym);
+            if offset < self.symlen(left) + 1 {
+                sym = left;
+            } else {
+                offset -= self.symlen(left) + 1;
+                sym = self.right(bytes, sym);
+            }
+        }
+        self.left(bytes, sym) as i32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+/// the parsed contents of a table file
+struct TableData {
+    bytes: Vec<u8>,
+    items: [[PairsData; 4]; 2],
+    /// start of the DTZ value maps
+    map: usize,
+}
+
+impl TableData {
+    fn parse(info: &TableInfo, kind: TableKind, bytes: Vec<u8>) -> Option<Self> {
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        if bytes.len() < 5 || bytes[..4] != magic {
+            return None;
+        }
+
+        let mut at = 4;
+        let flags = bytes[at];
+        let split = info.key != info.key2;
+        if (flags & 2 != 0) != info.has_pawns || (flags & 1 != 0) != split {
+            return None;
+        }
+        at += 1;
+
+        let sides = if kind == TableKind::Wdl && split {
+            2
+        } else {
+            1
+        };
+        let files = if info.has_pawns { 4 } else { 1 };
+        let pp = info.has_pawns && info.pawn_count[1] > 0;
+        let mut items: [[PairsData; 4]; 2] = Default::default();
+
+        for file in 0..files {
+            let second = if pp { byte(&bytes, at + 1) } else { 0xff };
+            let order = [
+                [(byte(&bytes, at) & 0xf) as usize, (second & 0xf) as usize],
+                [(byte(&bytes, at) >> 4) as usize, (second >> 4) as usize],
+            ];
+            at += 1 + pp as usize;
+            for k in 0..info.piece_count {
+                for (side, side_items) in items.iter_mut().enumerate().take(sides) {
+                    side_items[file].pieces[k] = if side == 1 {
+                        byte(&bytes, at) >> 4
+                    } else {
+                        byte(&bytes, at) & 0xf
+                    };
+                }
+                at += 1;
+            }
+            for (side, side_items) in items.iter_mut().enumerate().take(sides) {
+                side_items[file].set_groups(info, order[side], file);
+            }
+        }
+        at += at & 1;
+
+        for file in 0..files {
+            for side_items in items.iter_mut().take(sides) {
+                at = side_items[file].set_sizes(&bytes, at)?;
+            }
+        }
+
+        let map = at;
+        if kind == TableKind::Dtz {
+            for file in 0..files {
+                let d = &mut items[0][file];
+                if d.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & FLAG_WIDE != 0 {
+                    at += at & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = (at - map) / 2 + 1;
+                        at += 2 * u16_le(&bytes, at) as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = at - map + 1;
+                        at += byte(&bytes, at) as usize + 1;
+                    }
+                }
+            }
+            at += at & 1;
+        }
+
+        for file in 0..files {
+            for side_items in items.iter_mut().take(sides) {
+                side_items[file].sparse_index = at;
+                at += side_items[file].sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for side_items in items.iter_mut().take(sides) {
+                side_items[file].block_length = at;
+                at += side_items[file].block_length_size * 2;
+            }
+        }
+        for file in 0..files {
+            for side_items in items.iter_mut().take(sides) {
+                at = (at + 0x3f) & !0x3f;
+                side_items[file].data = at;
+                at += side_items[file].num_blocks * side_items[file].block_size;
+            }
+        }
+        if at > bytes.len() {
+            return None;
+        }
+
+        Some(Self { bytes, items, map })
+    }
+
+    fn get(&self, kind: TableKind, stm: usize, file: usize) -> &PairsData {
+        let side = match kind {
+            TableKind::Wdl => stm % 2,
+            TableKind::Dtz => 0,
+        };
+        &self.items[side][file]
+    }
+}
+
+/// one `.rtbw` or `.rtbz` file, read when it is first probed
+struct Table {
+    kind: TableKind,
+    path: PathBuf,
+    data: OnceLock<Option<TableData>>,
+}
+
+impl Table {
+    fn new(kind: TableKind, path: PathBuf) -> Self {
+        Self {
+            kind,
+            path,
+            data: OnceLock::new(),
+        }
+    }
+
+    fn data(&self, info: &TableInfo) -> Option<&TableData> {
+        self.data
+            .get_or_init(|| {
+                let bytes = fs::read(&self.path).ok()?;
+                TableData::parse(info, self.kind, bytes)
+            })
+            .as_ref()
+    }
+}
+
+struct TableEntry {
+    info: TableInfo,
+    wdl: Table,
+    dtz: Option<Table>,
+}
+
+enum TableValue {
+    Value(i32),
+    /// a DTZ table only stores one side to move and this is the other one
+    ChangeStm,
+}
+
+fn is_capture(board: &Board, mov: ChessMove) -> bool {
+    board.piece_on(mov.get_dest()).is_some()
+        || (board.piece_on(mov.get_source()) == Some(Piece::Pawn)
+            && mov.get_source().get_file() != mov.get_dest().get_file())
+}
+
+fn is_zeroing(board: &Board, mov: ChessMove) -> bool {
+    is_capture(board, mov) || board.piece_on(mov.get_source()) == Some(Piece::Pawn)
+}
+
+/// DTZ of the move before a zeroing move, given the result after it
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+/// every table found in the `SyzygyPath` directories
+#[derive(Default)]
+pub struct Tablebases {
+    /// keyed by the material with white first, so every table is in here twice
+    tables: HashMap<String, Arc<TableEntry>>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    /// scans the directories in `paths`, separated like the `PATH` environment variable
+    pub fn new(paths: &str) -> io::Result<Self> {
+        let mut tablebases = Self::default();
+        for dir in env::split_paths(paths) {
+            tablebases.add_dir(&dir)?;
+        }
+        Ok(tablebases)
+    }
+
+    fn add_dir(&mut self, dir: &Path) -> io::Result<()> {
+        for file in fs::read_dir(dir)? {
+            let path = file?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("rtbw") {
+                continue;
+            }
+            let Some(info) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(TableInfo::from_name)
+            else {
+                continue;
+            };
+
+            let dtz_path = path.with_extension("rtbz");
+            let dtz = dtz_path
+                .exists()
+                .then(|| Table::new(TableKind::Dtz, dtz_path));
+            self.max_pieces = self.max_pieces.max(info.piece_count);
+            let entry = Arc::new(TableEntry {
+                wdl: Table::new(TableKind::Wdl, path),
+                dtz,
+                info,
+            });
+            self.tables.insert(entry.info.key.clone(), entry.clone());
+            self.tables.insert(entry.info.key2.clone(), entry);
+        }
+        Ok(())
+    }
+
+    /// number of different tables found
+    pub fn len(&self) -> usize {
+        let mut names = self
+            .tables
+            .values()
+            .map(|e| e.info.key.as_str())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// the most pieces of any table found
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// tables know nothing about castling and can only hold that many pieces
+    pub fn can_probe(&self, board: &Board) -> bool {
+        board.combined().popcnt() as usize <= self.max_pieces
+            && board.castle_rights(Color::White) == CastleRights::NoRights
+            && board.castle_rights(Color::Black) == CastleRights::NoRights
+    }
+
+    fn probe_table(&self, board: &Board, kind: TableKind, wdl: Wdl) -> Option<TableValue> {
+        // KvK has no file
+        if board.combined().popcnt() == 2 {
+            return Some(TableValue::Value(0));
+        }
+        let entry = self.tables.get(&material_code(board))?;
+        let table = match kind {
+            TableKind::Wdl => &entry.wdl,
+            TableKind::Dtz => entry.dtz.as_ref()?,
+        };
+        let data = table.data(&entry.info)?;
+        Some(do_probe_table(board, &entry.info, kind, data, wdl))
+    }
+
+    /// The stored value is not trusted on its own: a position with a winning capture may store
+    /// anything, and en passant is not in the tables at all. The captures (and for DTZ the pawn
+    /// moves) are searched as well, the second value tells if the best move zeroes the 50-move
+    /// counter.
+    fn search(&self, board: &Board, check_zeroing: bool) -> Option<(Wdl, bool)> {
+        let moves = MoveGen::new_legal(board);
+        let total = moves.len();
+        let mut best = Wdl::Loss;
+        let mut move_count = 0;
+
+        for mov in moves {
+            let pawn_move = board.piece_on(mov.get_source()) == Some(Piece::Pawn);
+            if !is_capture(board, mov) && (!check_zeroing || !pawn_move) {
+                continue;
+            }
+            move_count += 1;
+            let (value, _) = self.search(&board.make_move_new(mov), false)?;
+            let value = -value;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        let no_more_moves = move_count > 0 && move_count == total;
+        let value = if no_more_moves {
+            best
+        } else {
+            match self.probe_table(board, TableKind::Wdl, Wdl::Draw)? {
+                TableValue::Value(v) => Wdl::from_i32(v),
+                TableValue::ChangeStm => return None,
+            }
+        };
+
+        if best >= value {
+            Some((best, best > Wdl::Draw || no_more_moves))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    /// win, draw or loss for the side to move, `None` when the table is missing
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        self.search(board, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Plies to the next capture or pawn move with best play, positive when the side to move
+    /// wins and off by one where the 50-move rule does not matter. 0 is a draw.
+    pub fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        let (wdl, zeroing) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        match self.probe_table(board, TableKind::Dtz, wdl)? {
+            TableValue::Value(dtz) => {
+                let cursed = matches!(wdl, Wdl::BlessedLoss | Wdl::CursedWin);
+                Some((dtz + 100 * cursed as i32) * wdl.to_i32().signum())
+            }
+            TableValue::ChangeStm => {
+                // the table stores the other side to move, look one ply ahead
+                let mut min_dtz = 0xffff;
+                for mov in MoveGen::new_legal(board) {
+                    let zeroing = is_zeroing(board, mov);
+                    let next = board.make_move_new(mov);
+                    let mut dtz = if zeroing {
+                        -dtz_before_zeroing(self.search(&next, false)?.0)
+                    } else {
+                        -self.probe_dtz(&next)?
+                    };
+                    if dtz == 1 && next.status() == BoardStatus::Checkmate {
+                        min_dtz = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < min_dtz && dtz.signum() == wdl.to_i32().signum() {
+                        min_dtz = dtz;
+                    }
+                }
+                Some(if min_dtz == 0xffff { -1 } else { min_dtz })
+            }
+        }
+    }
+
+    /// Ranks every root move with the DTZ tables and keeps the best ones: wins that can be
+    /// converted before the 50-move rule, then the longest losses. `halfmove_clock` is the
+    /// 50-move counter of the root position.
+    pub fn root_moves(&self, board: &Board, halfmove_clock: u32) -> Option<Vec<ChessMove>> {
+        let cnt50 = halfmove_clock as i32;
+        let mut ranked = Vec::new();
+        for mov in MoveGen::new_legal(board) {
+            let next = board.make_move_new(mov);
+            let mut dtz = if is_zeroing(board, mov) {
+                dtz_before_zeroing(-self.probe_wdl(&next)?)
+            } else {
+                let dtz = -self.probe_dtz(&next)?;
+                dtz + dtz.signum()
+            };
+            if dtz == 2 && next.status() == BoardStatus::Checkmate {
+                dtz = 1;
+            }
+
+            let rank = if dtz > 0 {
+                if dtz + cnt50 <= 99 {
+                    MAX_DTZ
+                } else {
+                    MAX_DTZ - (dtz + cnt50)
+                }
+            } else if dtz < 0 {
+                if -dtz * 2 + cnt50 < 100 {
+                    -MAX_DTZ
+                } else {
+                    -MAX_DTZ + (-dtz + cnt50)
+                }
+            } else {
+                0
+            };
+            ranked.push((mov, rank));
+        }
+
+        let best = ranked.iter().map(|(_, rank)| *rank).max()?;
+        Some(
+            ranked
+                .into_iter()
+                .filter(|(_, rank)| *rank == best)
+                .map(|(mov, _)| mov)
+                .collect(),
+        )
+    }
+}
+
+fn do_probe_table(
+    board: &Board,
+    info: &TableInfo,
+    kind: TableKind,
+    data: &TableData,
+    wdl: Wdl,
+) -> TableValue {
+    let e = &*ENCODING;
+
+    // a table with the same material for both sides only stores white to move, and every
+    // table has the stronger side as white: flip colours and ranks otherwise
+    let black_to_move = board.side_to_move() == Color::Black;
+    let symmetric_black_to_move = info.key == info.key2 && black_to_move;
+    let black_stronger = material_code(board) != info.key;
+    let flip = symmetric_black_to_move || black_stronger;
+    let flip_color = if flip { 8 } else { 0 };
+    let flip_squares = if flip { 56 } else { 0 };
+    let stm = flip as usize ^ black_to_move as usize;
+
+    let mut squares = [0usize; MAX_PIECES];
+    let mut pieces = [0u8; MAX_PIECES];
+    let mut size = 0;
+    let mut lead_pawns_cnt = 0;
+    let mut lead_pawns = EMPTY;
+    let mut tb_file = 0;
+
+    // with pawns the table is split by the file of the leading pawn
+    if info.has_pawns {
+        let lead = data.get(kind, 0, 0).pieces[0] ^ flip_color;
+        let color = if lead & 8 != 0 {
+            Color::Black
+        } else {
+            Color::White
+        };
+        lead_pawns = board.pieces(Piece::Pawn) & board.color_combined(color);
+        for sq in lead_pawns {
+            squares[size] = sq.to_index() ^ flip_squares;
+            size += 1;
+        }
+        lead_pawns_cnt = size;
+        let leading = (0..lead_pawns_cnt)
+            .max_by_key(|&i| e.map_pawns[squares[i]])
+            .unwrap_or(0);
+        squares.swap(0, leading);
+        tb_file = file_of(squares[0]).min(7 - file_of(squares[0]));
+    }
+
+    if kind == TableKind::Dtz {
+        let flags = data.get(kind, stm, tb_file).flags;
+        let symmetric_no_pawns = info.key == info.key2 && !info.has_pawns;
+        if (flags & FLAG_STM) as usize != stm && !symmetric_no_pawns {
+            return TableValue::ChangeStm;
+        }
+    }
+
+    for sq in *board.combined() ^ lead_pawns {
+        squares[size] = sq.to_index() ^ flip_squares;
+        pieces[size] = piece_code(board, sq) ^ flip_color;
+        size += 1;
+    }
+
+    let d = data.get(kind, stm, tb_file);
+
+    // same piece order as the table
+    for i in lead_pawns_cnt..size.saturating_sub(1) {
+        for j in i + 1..size {
+            if d.pieces[i] == pieces[j] {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+                break;
+            }
+        }
+    }
+
+    // the leading piece goes to files a to d
+    if file_of(squares[0]) > 3 {
+        for sq in squares.iter_mut().take(size) {
+            *sq ^= 7;
+        }
+    }
+
+    let mut idx;
+    if info.has_pawns {
+        idx = e.lead_pawn_idx[lead_pawns_cnt][squares[0]];
+        squares[1..lead_pawns_cnt].sort_by_key(|&sq| e.map_pawns[sq]);
+        for i in 1..lead_pawns_cnt {
+            idx += e.binomial[i][e.map_pawns[squares[i]]];
+        }
+    } else {
+        // and below the fifth rank
+        if rank_of(squares[0]) > 3 {
+            for sq in squares.iter_mut().take(size) {
+                *sq ^= 56;
+            }
+        }
+
+        // the first leading piece off the a1-h8 diagonal goes below it
+        for i in 0..d.group_len[0] {
+            let off = off_a1h8(squares[i]);
+            if off == 0 {
+                continue;
+            }
+            if off > 0 {
+                for sq in squares.iter_mut().take(size).skip(i) {
+                    *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                }
+            }
+            break;
+        }
+
+        if info.has_unique_pieces {
+            let (s0, s1, s2) = (squares[0], squares[1], squares[2]);
+            let adjust1 = (s1 > s0) as usize;
+            let adjust2 = (s2 > s0) as usize + (s2 > s1) as usize;
+            idx = if off_a1h8(s0) != 0 {
+                ((e.map_a1d1d4[s0] * 63 + (s1 - adjust1)) * 62 + s2 - adjust2) as u64
+            } else if off_a1h8(s1) != 0 {
+                ((6 * 63 + rank_of(s0) * 28 + e.map_b1h1h7[s1]) * 62 + s2 - adjust2) as u64
+            } else if off_a1h8(s2) != 0 {
+                (6 * 63 * 62
+                    + 4 * 28 * 62
+                    + rank_of(s0) * 7 * 28
+                    + (rank_of(s1) - adjust1) * 28
+                    + e.map_b1h1h7[s2]) as u64
+            } else {
+                (6 * 63 * 62
+                    + 4 * 28 * 62
+                    + 4 * 7 * 28
+                    + rank_of(s0) * 7 * 6
+                    + (rank_of(s1) - adjust1) * 6
+                    + (rank_of(s2) - adjust2)) as u64
+            };
+        } else {
+            idx = e.map_kk[e.map_a1d1d4[squares[0]]][squares[1]];
+        }
+    }
+
+    // the remaining groups, each square counted without the squares of earlier groups
+    idx *= d.group_idx[0];
+    let mut start = d.group_len[0];
+    let mut remaining_pawns = info.has_pawns && info.pawn_count[1] > 0;
+    let mut next = 1;
+    while next <= MAX_PIECES && d.group_len[next] != 0 {
+        let len = d.group_len[next];
+        squares[start..start + len].sort_unstable();
+        let mut n = 0;
+        for i in 0..len {
+            let sq = squares[start + i];
+            let adjust = squares[..start].iter().filter(|s| sq > **s).count();
+            let pawn_rank = if remaining_pawns { 8 } else { 0 };
+            n += e.binomial[i + 1][sq - adjust - pawn_rank];
+        }
+        remaining_pawns = false;
+        idx += n * d.group_idx[next];
+        start += len;
+        next += 1;
+    }
+
+    let value = d.decompress(&data.bytes, idx);
+    match kind {
+        TableKind::Wdl => TableValue::Value(value - 2),
+        TableKind::Dtz => TableValue::Value(map_dtz(data, tb_file, value, wdl)),
+    }
+}
+
+/// DTZ tables may store an index into a value map and may count moves instead of plies
+fn map_dtz(data: &TableData, file: usize, value: i32, wdl: Wdl) -> i32 {
+    let d = data.get(TableKind::Dtz, 0, file);
+    let mut value = value;
+    if d.flags & FLAG_MAPPED != 0 {
+        let map = match wdl {
+            Wdl::Win | Wdl::Draw => 0,
+            Wdl::Loss => 1,
+            Wdl::CursedWin => 2,
+            Wdl::BlessedLoss => 3,
+        };
+        let i = d.map_idx[map] + value as usize;
+        value = if d.flags & FLAG_WIDE != 0 {
+            u16_le(&data.bytes, data.map + 2 * i) as i32
+        } else {
+            byte(&data.bytes, data.map + i) as i32
+        };
+    }
+
+    let in_moves = match wdl {
+        Wdl::Win => d.flags & FLAG_WIN_PLIES == 0,
+        Wdl::Loss => d.flags & FLAG_LOSS_PLIES == 0,
+        Wdl::CursedWin | Wdl::BlessedLoss => true,
+        Wdl::Draw => false,
+    };
+    if in_moves {
+        value *= 2;
+    }
+    value + 1
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chess::Board;
+
+    use super::*;
+
+    #[test]
+    fn encoding_tables() {
+        let e = &*ENCODING;
+        let kings = e.map_kk.iter().flatten().max().unwrap() + 1;
+        assert_eq!(kings, 462);
+        assert_eq!(e.binomial[2][5], 10);
+        assert_eq!(e.binomial[5][63], 7_028_847);
+        // files are filled from the edge inwards, a2 first and e7 last
+        assert_eq!(e.map_pawns[8], 47);
+        assert_eq!(e.map_pawns[15], 46);
+        assert_eq!(e.map_pawns[52], 0);
+        // a single leading pawn has one place per rank
+        assert!(e.lead_pawns_size[1].iter().all(|size| *size == 6));
+    }
+
+    #[test]
+    fn table_names() {
+        let info = TableInfo::from_name("KRPvKR").unwrap();
+        assert_eq!(info.key2, "KRvKRP");
+        assert_eq!(info.piece_count, 5);
+        assert!(info.has_pawns);
+        assert_eq!(info.pawn_count, [1, 0]);
+
+        let info = TableInfo::from_name("KPvKPP").unwrap();
+        assert_eq!(info.pawn_count, [1, 2]);
+        assert!(!TableInfo::from_name("KRRvKRR").unwrap().has_unique_pieces);
+        assert!(TableInfo::from_name("KXvK").is_none());
+        assert!(TableInfo::from_name("KQQQQvKQQ").is_none());
+
+        let board = Board::from_str("8/8/8/8/8/3k4/2rp4/4K2R w - - 0 1").unwrap();
+        assert_eq!(material_code(&board), "KRvKRP");
+    }
+
+    #[test]
+    fn probing_without_tables() {
+        let tablebases = Tablebases::default();
+        let bare_kings = Board::from_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(tablebases.probe_wdl(&bare_kings), Some(Wdl::Draw));
+
+        let kqk = Board::from_str("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        assert_eq!(tablebases.probe_wdl(&kqk), None);
+        assert!(!tablebases.can_probe(&kqk));
+    }
+
+    /// Reads the KPvK, KRvK and KQvK tables from `test-data/syzygy`, see the README there.
+    #[test]
+    #[ignore = "the KPvK, KRvK and KQvK tables are not in test-data/syzygy yet"]
+    fn probes_local_tables() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test-data/syzygy");
+        let tablebases = Tablebases::new(path).unwrap();
+        assert_eq!(tablebases.max_pieces(), 3);
+
+        let wdl = |fen: &str| tablebases.probe_wdl(&Board::from_str(fen).unwrap());
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/3QK3 w - - 0 1"), Some(Wdl::Win));
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/3QK3 b - - 0 1"), Some(Wdl::Loss));
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"), Some(Wdl::Win));
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/R3K3 b - - 0 1"), Some(Wdl::Loss));
+        // the black king takes the rook
+        assert_eq!(wdl("8/8/8/8/8/3k4/3R4/7K b - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(wdl("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), Some(Wdl::Loss));
+        assert_eq!(wdl("4k3/8/4P3/4K3/8/8/8/8 w - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(wdl("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1"), Some(Wdl::Win));
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/2BNK3 w - - 0 1"), None);
+
+        // the bitbase and the tables agree on every king and pawn ending
+        for pawn in 8..56 {
+            for white_king in 0..64 {
+                for black_king in [0, 7, 27, 36, 56, 63] {
+                    let mut squares = [pawn, white_king, black_king];
+                    squares.sort_unstable();
+                    if squares.windows(2).any(|w| w[0] == w[1]) {
+                        continue;
+                    }
+                    let board = chess::BoardBuilder::new()
+                        .piece(square(pawn), Piece::Pawn, Color::White)
+                        .piece(square(white_king), Piece::King, Color::White)
+                        .piece(square(black_king), Piece::King, Color::Black)
+                        .side_to_move(Color::White)
+                        .try_into();
+                    let Ok(board): Result<Board, _> = board else {
+                        continue;
+                    };
+                    let tb = tablebases.probe_wdl(&board) == Some(Wdl::Win);
+                    assert_eq!(crate::kpk::probe_board(&board), Some(tb), "{board}");
+                }
+            }
+        }
+
+        let dtz = |fen: &str| tablebases.probe_dtz(&Board::from_str(fen).unwrap());
+        // mate in one and a promotion are both a single ply from the next zeroing move
+        assert_eq!(dtz("7k/8/6K1/8/8/8/8/Q7 w - - 0 1"), Some(1));
+        assert_eq!(dtz("7k/8/6K1/8/8/8/8/R7 w - - 0 1"), Some(1));
+        assert_eq!(dtz("8/4P3/8/8/8/8/k7/4K3 w - - 0 1"), Some(1));
+        assert_eq!(dtz("8/8/8/8/8/3k4/3R4/7K b - - 0 1"), Some(0));
+        assert!(dtz("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap() > 1);
+        assert!(dtz("4k3/8/8/8/8/8/8/3QK3 b - - 0 1").unwrap() < 0);
+    }
+}
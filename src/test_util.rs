@@ -0,0 +1,13 @@
+//! Helpers shared by the tests of several modules.
+
+use std::str::FromStr;
+
+use chess::ChessMove;
+
+/// the moves of a line written in UCI notation, `["e2e4", "e7e5"]`
+pub(crate) fn line(moves: &[&str]) -> Vec<ChessMove> {
+    moves
+        .iter()
+        .map(|m| ChessMove::from_str(m).unwrap())
+        .collect()
+}
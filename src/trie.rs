@@ -1,26 +1,316 @@
-use std::{collections::HashMap, fmt::Display, hash::Hash};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt::Display,
+    hash::Hash,
+};
+
+/// the longest line a stored trie may hold, anything deeper is taken for a corrupt file
+pub const MAX_DEPTH: usize = 1024;
+
+/// the fixed size binary form of the items and payloads of a stored trie
+pub trait TrieCodec: Sized {
+    /// bytes taken by one encoded value
+    const SIZE: usize;
+
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// `None` if `bytes` do not hold a valid value
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+impl TrieCodec for () {
+    const SIZE: usize = 0;
+
+    fn encode(&self, _out: &mut Vec<u8>) {}
+
+    fn decode(_bytes: &[u8]) -> Option<Self> {
+        Some(())
+    }
+}
+
+/// why stored bytes could not be read back, with the offset of the bad part
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrieError {
+    Truncated {
+        offset: usize,
+    },
+    BadItem {
+        offset: usize,
+    },
+    BadPayload {
+        offset: usize,
+    },
+    BadFlags {
+        offset: usize,
+    },
+    Duplicate {
+        offset: usize,
+    },
+    TrailingBytes {
+        offset: usize,
+    },
+    /// a line longer than `MAX_DEPTH`
+    TooDeep {
+        offset: usize,
+    },
+}
+
+impl Display for TrieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrieError::Truncated { offset } => write!(f, "data ends early at byte {offset}"),
+            TrieError::BadItem { offset } => write!(f, "invalid item at byte {offset}"),
+            TrieError::BadPayload { offset } => write!(f, "invalid payload at byte {offset}"),
+            TrieError::BadFlags { offset } => write!(f, "invalid node flags at byte {offset}"),
+            TrieError::Duplicate { offset } => write!(f, "duplicate entry at byte {offset}"),
+            TrieError::TrailingBytes { offset } => {
+                write!(f, "unexpected data after byte {offset}")
+            }
+            TrieError::TooDeep { offset } => {
+                write!(f, "line longer than {MAX_DEPTH} items at byte {offset}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TrieError {}
+
+/// reads stored values one after the other, keeping track of the offset for errors
+pub(crate) struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    pub(crate) fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], TrieError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + len)
+            .ok_or(TrieError::Truncated {
+                offset: self.offset,
+            })?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, TrieError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, TrieError> {
+        Ok(u16::from_be_bytes(
+            self.take(2)?.try_into().expect("two bytes"),
+        ))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, TrieError> {
+        Ok(u32::from_be_bytes(
+            self.take(4)?.try_into().expect("four bytes"),
+        ))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, TrieError> {
+        Ok(u64::from_be_bytes(
+            self.take(8)?.try_into().expect("eight bytes"),
+        ))
+    }
+
+    pub(crate) fn item<C: TrieCodec>(&mut self) -> Result<C, TrieError> {
+        let offset = self.offset;
+        C::decode(self.take(C::SIZE)?).ok_or(TrieError::BadItem { offset })
+    }
+
+    pub(crate) fn payload<C: TrieCodec>(&mut self) -> Result<C, TrieError> {
+        let offset = self.offset;
+        C::decode(self.take(C::SIZE)?).ok_or(TrieError::BadPayload { offset })
+    }
+
+    /// fails unless every byte has been read
+    pub(crate) fn finish(&self) -> Result<(), TrieError> {
+        match self.offset == self.bytes.len() {
+            true => Ok(()),
+            false => Err(TrieError::TrailingBytes {
+                offset: self.offset,
+            }),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
-pub struct Node<T: Copy + Clone + Eq + Hash> {
-    children: HashMap<T, Node<T>>,
+pub struct Node<T: Copy + Clone + Eq + Hash, P = ()> {
+    children: HashMap<T, Node<T, P>>,
     // refers to end of opening
     is_end: bool,
+    /// whatever the user keeps about the line ending at this node
+    payload: P,
 }
 
-impl<T: Copy + Clone + Eq + Hash> Default for Node<T> {
+impl<T: Copy + Clone + Eq + Hash, P: Default> Default for Node<T, P> {
     fn default() -> Self {
         Self {
             children: Default::default(),
             is_end: Default::default(),
+            payload: Default::default(),
         }
     }
 }
 
-impl<T: Copy + Clone + Hash + Eq + Display> Node<T> {
-    fn print(&self, prefix: &str) {
-        for (ch, node) in &self.children {
-            let new_prefix = format!("{}{}", prefix, ch);
-            println!("a{}", new_prefix);
+/// A long line is a deep chain of nodes, dropping them one by one keeps it off the stack.
+impl<T: Copy + Clone + Eq + Hash, P> Drop for Node<T, P> {
+    fn drop(&mut self) {
+        let mut stack = self
+            .children
+            .drain()
+            .map(|(_, node)| node)
+            .collect::<Vec<_>>();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.children.drain().map(|(_, node)| node));
+        }
+    }
+}
+
+impl<T: Copy + Clone + Eq + Hash, P> Node<T, P> {
+    pub fn children(&self) -> &HashMap<T, Node<T, P>> {
+        &self.children
+    }
+
+    pub fn payload(&self) -> &P {
+        &self.payload
+    }
+
+    pub fn payload_mut(&mut self) -> &mut P {
+        &mut self.payload
+    }
+
+    pub fn is_end(&self) -> bool {
+        self.is_end
+    }
+
+    /// the lines of the subtree, from this node
+    pub fn lines(&self) -> Lines<'_, T, P> {
+        Lines {
+            stack: vec![(0, None, self)],
+            path: Vec::new(),
+        }
+    }
+
+    /// the nodes of the subtree, this one included
+    fn size(&self) -> usize {
+        let mut size = 0;
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            size += 1;
+            stack.extend(node.children.values());
+        }
+        size
+    }
+}
+
+/// Depth first iterator over the lines of a trie, each with the payload of its last node. A
+/// line comes before the longer lines it starts, siblings come in no particular order.
+pub struct Lines<'a, T: Copy + Clone + Eq + Hash, P> {
+    /// the nodes still to visit, with their depth and the item leading to them
+    stack: Vec<(usize, Option<T>, &'a Node<T, P>)>,
+    /// the items leading to the node visited last
+    path: Vec<T>,
+}
+
+impl<'a, T: Copy + Clone + Eq + Hash, P> Iterator for Lines<'a, T, P> {
+    type Item = (Vec<T>, &'a P);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((depth, item, node)) = self.stack.pop() {
+            self.path.truncate(depth);
+            self.path.extend(item);
+            let depth = self.path.len();
+            self.stack.extend(
+                node.children
+                    .iter()
+                    .map(|(item, child)| (depth, Some(*item), child)),
+            );
+            if node.is_end {
+                return Some((self.path.clone(), &node.payload));
+            }
+        }
+        None
+    }
+}
+
+/// how big a trie is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrieStats {
+    /// the nodes below the root
+    pub nodes: usize,
+    pub lines: usize,
+    /// nodes without children
+    pub leaves: usize,
+    /// items in the longest line
+    pub max_depth: usize,
+}
+
+/// node flag of a line that ends at the node
+const END_FLAG: u8 = 1;
+
+impl<T: Copy + Clone + Eq + Hash + TrieCodec, P: TrieCodec> Node<T, P> {
+    /// The node and everything below it, depth first: the flags, the payload, the number of
+    /// children and then each child after its item. Children are sorted by their encoded item so
+    /// the same trie always gives the same bytes.
+    fn encode(&self, out: &mut Vec<u8>) {
+        // the nodes still to write, each after its encoded item
+        let mut stack = vec![(Vec::new(), self)];
+        while let Some((item, node)) = stack.pop() {
+            out.extend(item);
+            out.push(if node.is_end { END_FLAG } else { 0 });
+            node.payload.encode(out);
+            out.extend((node.children.len() as u16).to_be_bytes());
+            let mut children = node
+                .children
+                .iter()
+                .map(|(item, node)| {
+                    let mut bytes = Vec::with_capacity(T::SIZE);
+                    item.encode(&mut bytes);
+                    (bytes, node)
+                })
+                .collect::<Vec<_>>();
+            // reversed, the first child is popped first
+            children.sort_by(|(a, _), (b, _)| b.cmp(a));
+            stack.extend(children);
+        }
+    }
+
+    /// a node without its children and the number of children that follow it
+    fn decode(reader: &mut ByteReader) -> Result<(Self, u16), TrieError> {
+        let offset = reader.offset();
+        let is_end = match reader.u8()? {
+            0 => false,
+            END_FLAG => true,
+            _ => return Err(TrieError::BadFlags { offset }),
+        };
+        let payload = reader.payload()?;
+        let children = reader.u16()?;
+        let node = Self {
+            children: HashMap::with_capacity(children as usize),
+            is_end,
+            payload,
+        };
+        Ok((node, children))
+    }
+}
+
+impl<T: Copy + Clone + Hash + Eq + Display, P> Node<T, P> {
+    /// prints every line below the node on a row of its own, after `prefix`
+    pub fn print(&self, prefix: &str) {
+        for (line, _) in self.lines() {
+            let items = line.iter().map(T::to_string).collect::<Vec<_>>();
+            println!("{prefix}{}", items.join(" "));
         }
     }
 
@@ -34,18 +324,63 @@ impl<T: Copy + Clone + Hash + Eq + Display> Node<T> {
             node.pprint(&ch.to_string(), level + 1);
         }
     }
+}
 
-    pub fn childern(&self) -> &HashMap<T, Node<T>> {
-        &self.children
+/// A place in a trie kept as the items that lead there, so a shared trie is never changed to
+/// follow a game and the cursor can always go back. Items past the end of the trie are kept as
+/// well, taking them back returns into the trie.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrieCursor<T> {
+    path: Vec<T>,
+}
+
+impl<T: Copy + Clone + Eq + Hash> TrieCursor<T> {
+    pub fn new() -> Self {
+        Self { path: Vec::new() }
+    }
+
+    pub fn from_items(items: &[T]) -> Self {
+        Self {
+            path: items.to_vec(),
+        }
+    }
+
+    /// the items from the root to the cursor
+    pub fn path(&self) -> &[T] {
+        &self.path
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.path.push(item);
+    }
+
+    /// takes back the last item
+    pub fn pop(&mut self) -> Option<T> {
+        self.path.pop()
+    }
+
+    /// takes back everything after the first `len` items
+    pub fn rewind(&mut self, len: usize) {
+        self.path.truncate(len);
+    }
+
+    /// back to the root
+    pub fn reset(&mut self) {
+        self.path.clear();
+    }
+
+    /// the node of `trie` at the cursor, `None` once the path has left it
+    pub fn node<'a, P: Default + Clone>(&self, trie: &'a Trie<T, P>) -> Option<&'a Node<T, P>> {
+        trie.node(&self.path)
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct Trie<T: Copy + Eq + Clone + Hash> {
-    root: Node<T>,
+pub struct Trie<T: Copy + Eq + Clone + Hash, P = ()> {
+    root: Node<T, P>,
 }
 
-impl<T: Clone + Copy + Eq + Hash> Default for Trie<T> {
+impl<T: Clone + Copy + Eq + Hash, P: Default> Default for Trie<T, P> {
     fn default() -> Self {
         Self {
             root: Default::default(),
@@ -53,15 +388,21 @@ impl<T: Clone + Copy + Eq + Hash> Default for Trie<T> {
     }
 }
 
-impl<T: Copy + Clone + Eq + Hash> Trie<T> {
+impl<T: Copy + Clone + Eq + Hash, P: Default + Clone> Trie<T, P> {
     pub fn is_end(&self) -> bool {
         self.root.is_end
     }
     pub fn insert(&mut self, items: &[T]) {
+        self.insert_with(items, |_| {});
+    }
+
+    /// Inserts `items` as a line and calls `update` with the payload of every node on the way,
+    /// the root not included. A line stays one when a longer line starting with it is added.
+    pub fn insert_with(&mut self, items: &[T], mut update: impl FnMut(&mut P)) {
         let mut curr = &mut self.root;
         for i in items.iter() {
             curr = curr.children.entry(*i).or_default();
-            curr.is_end = false;
+            update(&mut curr.payload);
         }
         curr.is_end = true;
     }
@@ -70,32 +411,185 @@ impl<T: Copy + Clone + Eq + Hash> Trie<T> {
         self.root.children.contains_key(needle)
     }
 
-    pub fn change_root(&mut self, new_root_value: T) -> bool {
-        if let Some(new_root) = self.root.children.get(&new_root_value) {
-            self.root = new_root.clone();
-            true
-        } else {
-            false
+    /// whether `items` were inserted as a line
+    pub fn search(&self, items: &[T]) -> bool {
+        self.node(items).is_some_and(|node| node.is_end)
+    }
+
+    /// Removes the line `items` and the nodes only it needed, the lines it starts stay.
+    /// Returns whether it was a line.
+    pub fn remove(&mut self, items: &[T]) -> bool {
+        let Some(node) = self.node_mut(items) else {
+            return false;
+        };
+        if !std::mem::replace(&mut node.is_end, false) {
+            return false;
+        }
+        if !node.children.is_empty() {
+            return true;
         }
+        // the line hangs below the last node on its way that something else needs
+        let mut cut = 0;
+        let mut node = &self.root;
+        for (depth, item) in items.iter().enumerate() {
+            if node.is_end || node.children.len() > 1 {
+                cut = depth;
+            }
+            node = &node.children[item];
+        }
+        if let Some(node) = self.node_mut(&items[..cut]) {
+            node.children.remove(&items[cut]);
+        }
+        true
     }
 
-    fn search(&self, items: &[T]) -> bool {
+    /// every line with the payload of its last node, depth first
+    pub fn lines(&self) -> Lines<'_, T, P> {
+        self.root.lines()
+    }
+
+    /// Cuts every line at the first node whose payload fails `keep`, a line cut short ends
+    /// where it was cut. Returns the number of nodes removed.
+    pub fn prune(&mut self, keep: impl Fn(&P) -> bool) -> usize {
+        let mut removed = 0;
+        let mut stack = vec![(&mut self.root, true)];
+        while let Some((node, is_root)) = stack.pop() {
+            let had_children = !node.children.is_empty();
+            node.children.retain(|_, child| {
+                let kept = keep(&child.payload);
+                if !kept {
+                    removed += child.size();
+                }
+                kept
+            });
+            // a line cut short still holds up to where it was cut
+            if !is_root && had_children && node.children.is_empty() {
+                node.is_end = true;
+            }
+            stack.extend(node.children.values_mut().map(|child| (child, false)));
+        }
+        removed
+    }
+
+    /// adds the lines of `other`, `combine` merges the payloads of nodes both tries have
+    pub fn merge(&mut self, mut other: Trie<T, P>, mut combine: impl FnMut(&mut P, P)) {
+        let mut stack = vec![(&mut self.root, std::mem::take(&mut other.root))];
+        while let Some((node, mut other)) = stack.pop() {
+            node.is_end |= other.is_end;
+            combine(&mut node.payload, std::mem::take(&mut other.payload));
+            let mut both = HashMap::new();
+            for (item, child) in other.children.drain() {
+                match node.children.entry(item) {
+                    Entry::Occupied(_) => {
+                        both.insert(item, child);
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(child);
+                    }
+                }
+            }
+            for (item, child) in node.children.iter_mut() {
+                if let Some(other) = both.remove(item) {
+                    stack.push((child, other));
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self) -> TrieStats {
+        let mut stats = TrieStats::default();
+        let mut stack = vec![(0, &self.root)];
+        while let Some((depth, node)) = stack.pop() {
+            if depth > 0 {
+                stats.nodes += 1;
+            }
+            if node.is_end {
+                stats.lines += 1;
+            }
+            if node.children.is_empty() && depth > 0 {
+                stats.leaves += 1;
+            }
+            stats.max_depth = stats.max_depth.max(depth);
+            stack.extend(node.children.values().map(|child| (depth + 1, child)));
+        }
+        stats
+    }
+
+    /// the node `items` leads to from the root
+    pub fn node(&self, items: &[T]) -> Option<&Node<T, P>> {
         let mut current = &self.root;
         for i in items.iter() {
-            match current.children.get(i) {
-                Some(node) => current = node,
-                None => return false,
-            }
+            current = current.children.get(i)?;
         }
-        current.is_end
+        Some(current)
     }
 
-    pub fn root(&self) -> &Node<T> {
+    pub fn node_mut(&mut self, items: &[T]) -> Option<&mut Node<T, P>> {
+        let mut current = &mut self.root;
+        for i in items.iter() {
+            current = current.children.get_mut(i)?;
+        }
+        Some(current)
+    }
+
+    pub fn root(&self) -> &Node<T, P> {
         &self.root
     }
 }
 
-impl<T: Copy + Clone + Hash + Eq + Display> Trie<T> {
+impl<T: Copy + Clone + Eq + Hash + TrieCodec, P: TrieCodec> Trie<T, P> {
+    /// appends the binary form of the trie to `out`
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        self.root.encode(out);
+    }
+
+    /// Reads a trie written by `encode` from the start of `reader`. A line longer than
+    /// `MAX_DEPTH` is an error, the trie would be too deep to be of any use.
+    pub(crate) fn decode(reader: &mut ByteReader) -> Result<Self, TrieError> {
+        let (root, children) = Node::decode(reader)?;
+        // the nodes still missing children, with the item that leads to each and the count left
+        let mut stack = vec![(None, root, children)];
+        loop {
+            let (_, _, left) = stack.last_mut().expect("the root is popped last");
+            if *left > 0 {
+                *left -= 1;
+                let offset = reader.offset();
+                // the root is on the stack as well
+                if stack.len() > MAX_DEPTH {
+                    return Err(TrieError::TooDeep { offset });
+                }
+                let item = reader.item()?;
+                let (node, children) = Node::decode(reader)?;
+                stack.push((Some((item, offset)), node, children));
+                continue;
+            }
+            let (item, node, _) = stack.pop().expect("just looked at it");
+            let Some((item, offset)) = item else {
+                return Ok(Self { root: node });
+            };
+            let (_, parent, _) = stack.last_mut().expect("only the root has no item");
+            if parent.children.insert(item, node).is_some() {
+                return Err(TrieError::Duplicate { offset });
+            }
+        }
+    }
+
+    /// the trie stored in `bytes` and nothing else
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TrieError> {
+        let mut reader = ByteReader::new(bytes);
+        let trie = Self::decode(&mut reader)?;
+        reader.finish()?;
+        Ok(trie)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out
+    }
+}
+
+impl<T: Copy + Clone + Hash + Eq + Display, P> Trie<T, P> {
     pub fn print(&self) {
         self.root.pprint("", 0);
     }
@@ -106,11 +600,12 @@ mod tests {
     use std::str::FromStr;
 
     use super::*;
+    use crate::test_util::line;
     use chess::*;
 
     #[test]
     fn db_test() {
-        let mut db = Trie::default();
+        let mut db: Trie<ChessMove> = Trie::default();
         let chess_line_1 = vec![
             ChessMove::from_str("d2d4").unwrap(),
             ChessMove::from_str("g8f6").unwrap(),
@@ -135,8 +630,189 @@ mod tests {
         db.insert(&chess_line_2);
         db.insert(&chess_line_3);
 
-        assert!(db.change_root(ChessMove::from_str("d2d4").unwrap()));
-        assert!(db.change_root(ChessMove::from_str("d7d5").unwrap()));
-        assert!(db.change_root(ChessMove::from_str("c2c4").unwrap()));
+        let mut cursor = TrieCursor::new();
+        for mov in &chess_line_2[..3] {
+            cursor.push(*mov);
+            assert!(cursor.node(&db).is_some());
+        }
+        let children = cursor.node(&db).unwrap().children();
+        assert_eq!(children.len(), 2);
+
+        // leaving the trie and coming back
+        cursor.push(ChessMove::from_str("a7a6").unwrap());
+        assert!(cursor.node(&db).is_none());
+        cursor.pop();
+        assert!(cursor.node(&db).is_some());
+
+        cursor.rewind(1);
+        assert_eq!(cursor.node(&db).unwrap().children().len(), 2);
+        cursor.reset();
+        assert_eq!(cursor.path(), &[]);
+        assert!(cursor.node(&db).is_some());
+
+        let cursor = TrieCursor::from_items(&chess_line_1);
+        assert!(cursor.node(&db).unwrap().is_end());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let mut db: Trie<ChessMove> = Trie::default();
+        db.insert(&line(&["d2d4", "g8f6", "c2c4"]));
+        db.insert(&line(&["d2d4", "d7d5"]));
+        db.insert(&line(&["e2e4", "e7e5", "g1f3", "b8c6"]));
+        db.insert(&line(&["a7a8q"]));
+
+        let bytes = db.to_bytes();
+        let read = Trie::<ChessMove>::from_bytes(&bytes).unwrap();
+        assert_eq!(read.to_bytes(), bytes);
+        assert!(read.search(&line(&["d2d4", "d7d5"])));
+        assert!(read.search(&line(&["a7a8q"])));
+        assert!(!read.search(&line(&["d2d4"])));
+
+        // every cut short file is an error, never a panic
+        for len in 0..bytes.len() {
+            assert!(
+                Trie::<ChessMove>::from_bytes(&bytes[..len]).is_err(),
+                "{len}"
+            );
+        }
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(
+            Trie::<ChessMove>::from_bytes(&longer).unwrap_err(),
+            TrieError::TrailingBytes {
+                offset: bytes.len()
+            }
+        );
+        let mut flags = bytes.clone();
+        flags[0] = 7;
+        assert_eq!(
+            Trie::<ChessMove>::from_bytes(&flags).unwrap_err(),
+            TrieError::BadFlags { offset: 0 }
+        );
+        // a node claiming more children than there are bytes for
+        assert_eq!(
+            Trie::<ChessMove>::from_bytes(&[0, 0xff, 0xff]).unwrap_err(),
+            TrieError::Truncated { offset: 3 }
+        );
+    }
+
+    #[test]
+    fn deep_lines() {
+        let deep = vec!['a'; 300_000];
+        let mut trie = Trie::<char, u32>::default();
+        trie.insert_with(&deep, |count| *count += 1);
+        assert_eq!(trie.stats().max_depth, deep.len());
+        let mut other = Trie::default();
+        other.insert_with(&deep, |count| *count += 1);
+        trie.merge(other, |count, other| *count += other);
+        assert_eq!(*trie.node(&deep).unwrap().payload(), 2);
+        assert!(trie.remove(&deep));
+        assert!(trie.root().children().is_empty());
+        trie.insert_with(&deep, |count| *count += 1);
+        assert_eq!(trie.prune(|_| false), deep.len());
+
+        let e4 = line(&["e2e4"])[0];
+        let mut chain = Trie::<ChessMove>::default();
+        chain.insert(&vec![e4; MAX_DEPTH]);
+        let bytes = chain.to_bytes();
+        assert!(Trie::<ChessMove>::from_bytes(&bytes).is_ok());
+        chain.insert(&vec![e4; 300_000]);
+        assert!(matches!(
+            Trie::<ChessMove>::from_bytes(&chain.to_bytes()),
+            Err(TrieError::TooDeep { .. })
+        ));
+    }
+
+    /// a trie of words, each node counting the words through it
+    fn words(words: &[&str]) -> Trie<char, u32> {
+        let mut trie = Trie::default();
+        for word in words {
+            trie.insert_with(&word.chars().collect::<Vec<_>>(), |count| *count += 1);
+        }
+        trie
+    }
+
+    fn chars(word: &str) -> Vec<char> {
+        word.chars().collect()
+    }
+
+    fn sorted_lines(trie: &Trie<char, u32>) -> Vec<(String, u32)> {
+        let mut lines = trie
+            .lines()
+            .map(|(line, count)| (line.into_iter().collect(), *count))
+            .collect::<Vec<_>>();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn lines_and_removal() {
+        let mut trie = words(&["tea", "ten", "te", "to", "tea"]);
+        // a line that starts a longer one is still a line
+        assert!(trie.search(&chars("te")));
+        assert!(!trie.search(&chars("t")));
+        assert_eq!(
+            sorted_lines(&trie),
+            [("te", 4), ("tea", 2), ("ten", 1), ("to", 1)].map(|(w, n)| (w.to_string(), n))
+        );
+        // depth first, a line before the lines it starts
+        let order = trie.lines().map(|(line, _)| line).collect::<Vec<_>>();
+        let te = order.iter().position(|line| *line == chars("te")).unwrap();
+        let tea = order.iter().position(|line| *line == chars("tea")).unwrap();
+        assert!(te < tea);
+
+        assert!(trie.remove(&chars("te")));
+        assert!(!trie.remove(&chars("te")));
+        assert!(!trie.remove(&chars("tex")));
+        // the words it started are still there
+        assert!(trie.search(&chars("tea")));
+        assert!(trie.remove(&chars("to")));
+        assert!(trie.node(&chars("to")).is_none());
+        assert!(trie.remove(&chars("tea")));
+        assert!(trie.remove(&chars("ten")));
+        assert!(trie.root().children().is_empty());
+    }
+
+    #[test]
+    fn pruning() {
+        let mut trie = words(&[
+            "tea", "ten", "tent", "ten", "inn", "inn", "in", "xyz", "xyw",
+        ]);
+        // drop every node fewer than two words go through
+        let removed = trie.prune(|count| *count >= 2);
+        assert_eq!(removed, 4);
+        // "xy" was no word but the lines through it are cut there
+        assert_eq!(
+            sorted_lines(&trie),
+            [("in", 3), ("inn", 2), ("ten", 3), ("xy", 2)].map(|(w, n)| (w.to_string(), n))
+        );
+        assert_eq!(trie.prune(|_| true), 0);
+    }
+
+    #[test]
+    fn merging() {
+        let mut trie = words(&["tea", "to"]);
+        trie.merge(words(&["tea", "in"]), |count, other| *count += other);
+        assert_eq!(
+            sorted_lines(&trie),
+            [("in", 1), ("tea", 2), ("to", 1)].map(|(w, n)| (w.to_string(), n))
+        );
+        assert_eq!(*trie.node(&chars("t")).unwrap().payload(), 3);
+    }
+
+    #[test]
+    fn statistics() {
+        assert_eq!(Trie::<char>::default().stats(), TrieStats::default());
+        let trie = words(&["tea", "ten", "te", "inn"]);
+        assert_eq!(
+            trie.stats(),
+            TrieStats {
+                nodes: 7,
+                lines: 4,
+                leaves: 3,
+                max_depth: 3
+            }
+        );
     }
 }
@@ -0,0 +1,238 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+    thread,
+};
+
+use chess::Board;
+
+use crate::{eval::Evaluation, eval_params::EvalParams, game_state::GameState};
+
+/// a quiet position and the result of the game it was taken from, from white's point of view
+/// (1.0 win, 0.5 draw, 0.0 loss)
+#[derive(Debug, Clone)]
+pub struct TuningPosition {
+    pub board: Board,
+    pub result: f64,
+}
+
+fn parse_result(token: &str) -> Option<f64> {
+    let token = token.trim_matches(|c| matches!(c, '"' | ';' | '[' | ']' | '(' | ')'));
+    match token {
+        "1-0" | "1.0" => Some(1.0),
+        "0-1" | "0.0" => Some(0.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        _ => None,
+    }
+}
+
+impl FromStr for TuningPosition {
+    type Err = String;
+
+    /// Accepts EPD lines (`<fen> c9 "1-0";`) and FEN lines followed by a result such as `1-0`,
+    /// `[0.5]` or `0-1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = s.split_whitespace().collect::<Vec<_>>();
+        if tokens.len() < 5 {
+            return Err(format!("not a position: {s}"));
+        }
+
+        let result = tokens
+            .iter()
+            .rev()
+            .find_map(|t| parse_result(t))
+            .ok_or_else(|| format!("no game result in: {s}"))?;
+
+        // EPD only has the first four FEN fields, plain FEN lines have all six
+        let mut fen = tokens[..4].to_vec();
+        let clocks = tokens[4..]
+            .iter()
+            .take(2)
+            .take_while(|t| t.parse::<usize>().is_ok())
+            .copied()
+            .collect::<Vec<_>>();
+        if clocks.len() == 2 {
+            fen.extend(clocks);
+        } else {
+            fen.extend(["0", "1"]);
+        }
+
+        let board = Board::from_str(&fen.join(" ")).map_err(|e| format!("{e}: {s}"))?;
+        Ok(Self { board, result })
+    }
+}
+
+/// reads every position of an EPD or FEN+result file, skipping lines that cannot be parsed
+pub fn load_positions<P: AsRef<Path>>(path: P) -> io::Result<Vec<TuningPosition>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut positions = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Ok(position) = line.parse() {
+            positions.push(position);
+        }
+    }
+    Ok(positions)
+}
+
+/// static evaluation from white's point of view
+pub fn static_eval(board: &Board, params: &EvalParams) -> isize {
+    let mut game_state = GameState::new();
+    Evaluation::with_params(board, &mut game_state, params)
+        .trace(board)
+        .value()
+}
+
+/// maps a centipawn score to an expected game result
+pub fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+/// mean squared error between the game results and the sigmoid of the evaluation
+pub fn mean_squared_error(positions: &[TuningPosition], params: &EvalParams, k: f64) -> f64 {
+    if positions.is_empty() {
+        return 0.0;
+    }
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let chunk_size = positions.len().div_ceil(threads);
+
+    let total: f64 = thread::scope(|scope| {
+        let handles = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|p| {
+                            let predicted = sigmoid(static_eval(&p.board, params) as f64, k);
+                            (p.result - predicted).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("tuning thread panicked"))
+            .sum()
+    });
+    total / positions.len() as f64
+}
+
+/// finds the scaling constant `k` that best fits the current evaluation to the results
+pub fn find_k(positions: &[TuningPosition], params: &EvalParams) -> f64 {
+    let (mut lo, mut hi) = (0.0f64, 3.0f64);
+    for _ in 0..30 {
+        let m1 = lo + (hi - lo) / 3.0;
+        let m2 = hi - (hi - lo) / 3.0;
+        if mean_squared_error(positions, params, m1) < mean_squared_error(positions, params, m2) {
+            hi = m2;
+        } else {
+            lo = m1;
+        }
+    }
+    (lo + hi) / 2.0
+}
+
+/// Texel's local search: every weight is nudged up and down by one and the change is kept when
+/// it lowers the error. Stops after `iterations` passes or when a pass changes nothing.
+/// `report` is called after every pass with the pass number and the current error.
+pub fn tune<F: FnMut(usize, f64, &EvalParams)>(
+    positions: &[TuningPosition],
+    params: &EvalParams,
+    k: f64,
+    iterations: usize,
+    mut report: F,
+) -> EvalParams {
+    let mut best = params.clone();
+    let mut values = best.to_vec();
+    let mut best_error = mean_squared_error(positions, &best, k);
+
+    // the pawn value is the unit everything else is measured in, so it stays fixed
+    let tunable = 1..values.len();
+
+    for iteration in 1..=iterations {
+        let mut improved = false;
+        for idx in tunable.clone() {
+            for delta in [1, -1] {
+                values[idx] += delta;
+                let mut candidate = best.clone();
+                candidate.set_from_slice(&values);
+                let error = mean_squared_error(positions, &candidate, k);
+                if error < best_error {
+                    best_error = error;
+                    best = candidate;
+                    improved = true;
+                    break;
+                }
+                values[idx] -= delta;
+            }
+        }
+        report(iteration, best_error, &best);
+        if !improved {
+            break;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_epd_and_fen_lines() {
+        let epd: TuningPosition =
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - c9 \"1/2-1/2\";"
+                .parse()
+                .unwrap();
+        assert_eq!(epd.result, 0.5);
+
+        let fen: TuningPosition = "8/8/4k3/8/8/4K3/4P3/8 w - - 0 1 [1.0]".parse().unwrap();
+        assert_eq!(fen.result, 1.0);
+
+        let fen: TuningPosition = "8/8/4k3/8/8/4K3/4P3/8 w - - 0 1 0-1".parse().unwrap();
+        assert_eq!(fen.result, 0.0);
+
+        assert!("8/8/4k3/8/8/4K3/4P3/8 w - - 0 1"
+            .parse::<TuningPosition>()
+            .is_err());
+    }
+
+    #[test]
+    fn tuning_moves_a_corrupted_weight_back() {
+        // results that the default weights predict exactly, so they are the optimum
+        let k = 1.0;
+        let fitted = EvalParams::default();
+        let positions = [
+            "4k3/8/8/8/3N4/8/4P3/4K3 w - - 0 1",
+            "4k3/4p3/8/3n4/8/8/8/4K3 w - - 0 1",
+            "4k3/pp6/8/8/8/2N2N2/PP6/4K3 b - - 0 1",
+            "1n2k3/8/8/8/8/8/4PP2/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2K3 b - - 0 1",
+        ]
+        .iter()
+        .map(|fen| {
+            let board = Board::from_str(fen).unwrap();
+            let result = sigmoid(static_eval(&board, &fitted) as f64, k);
+            TuningPosition { board, result }
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(mean_squared_error(&positions, &fitted, k), 0.0);
+
+        let mut corrupted = fitted.clone();
+        corrupted.knight_value += 60;
+        let before = mean_squared_error(&positions, &corrupted, k);
+        let tuned = tune(&positions, &corrupted, k, 3, |_, _, _| {});
+        assert!(mean_squared_error(&positions, &tuned, k) < before);
+        assert!(tuned.knight_value < corrupted.knight_value);
+        assert!(tuned.knight_value > fitted.knight_value);
+    }
+}
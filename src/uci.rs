@@ -6,17 +6,45 @@ use std::{
     ops::Add,
 };
 
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 
-use chess::ChessMove;
+use chess::{Board, BoardStatus, ChessMove, Color};
+use rand::{rngs::StdRng, SeedableRng};
 
-use crate::engine::Engine;
+use crate::engine::{Engine, Evaluator};
+use crate::evaluation_value::EvaluationValue;
+use crate::BookPolicy;
+use crate::EvalParams;
+use crate::GameResult;
+use crate::Network;
 use crate::OpeningDatabase;
+use crate::PolyglotBook;
+use crate::Tablebases;
 
 pub struct UCI {
     engine: Engine,
     curr_think_time: u64,
-    opening_db: OpeningDatabase,
+    opening_db: Arc<OpeningDatabase>,
+    eval_params: EvalParams,
+    network: Option<Arc<Network>>,
+    use_nnue: bool,
+    tablebases: Option<Arc<Tablebases>>,
+    contempt: isize,
+    own_book: bool,
+    polyglot_book: Option<Arc<PolyglotBook>>,
+    book_policy: BookPolicy,
+    /// whether finished games update the book statistics
+    book_learning: bool,
+    /// where the book is saved after every learned game
+    learn_file: Option<String>,
+    /// the game being played as the last `position` command gave it, with the engine's own moves
+    /// played since
+    game_start: Board,
+    game_moves: Vec<ChessMove>,
+    /// the evaluation of the first search after the book, in centipawns for White
+    exit_eval: Option<isize>,
 }
 
 impl UCI {
@@ -24,13 +52,49 @@ impl UCI {
         Self {
             engine: Engine::new(),
             curr_think_time: 0,
-            opening_db: OpeningDatabase::new(),
+            opening_db: Arc::default(),
+            eval_params: EvalParams::default(),
+            network: None,
+            use_nnue: false,
+            tablebases: None,
+            contempt: 0,
+            own_book: true,
+            polyglot_book: None,
+            book_policy: BookPolicy::default(),
+            book_learning: false,
+            learn_file: None,
+            game_start: Board::default(),
+            game_moves: Vec::new(),
+            exit_eval: None,
         }
     }
 
+    fn evaluator(&self) -> Evaluator {
+        match (&self.network, self.use_nnue) {
+            (Some(net), true) => Evaluator::Nnue(net.clone()),
+            _ => Evaluator::Classic,
+        }
+    }
+
+    /// carries everything set through `setoption` over to a freshly created engine
+    fn with_options(&self, mut engine: Engine) -> Engine {
+        engine.set_eval_params(self.eval_params.clone());
+        engine.set_evaluator(self.evaluator());
+        engine.set_tablebases(self.tablebases.clone());
+        engine.set_contempt(self.contempt);
+        engine.set_own_book(self.own_book);
+        engine.set_polyglot_book(self.polyglot_book.clone());
+        engine.set_book_policy(self.book_policy);
+        // the random book moves go on from where the last game left off, a match played with
+        // the same seed then sees the same openings
+        engine.set_book_rng(self.engine.book_rng().clone());
+        engine.add_opening_db(self.opening_db.clone());
+        engine
+    }
+
     pub fn add_db(&mut self, opening_database: OpeningDatabase) {
-        self.opening_db = opening_database.clone();
-        self.engine.add_opening_db(opening_database);
+        self.opening_db = Arc::new(opening_database);
+        self.engine.add_opening_db(self.opening_db.clone());
     }
 
     pub fn rx(&mut self) {
@@ -54,17 +118,36 @@ impl UCI {
                         "uci" => {
                             self.tx("id name NotSoBrightBot");
                             self.tx("id author Sahil");
+                            self.tx("option name EvalFile type string default <empty>");
+                            self.tx("option name UseNNUE type check default false");
+                            self.tx("option name NNUEFile type string default <empty>");
+                            self.tx("option name SyzygyPath type string default <empty>");
+                            self.tx("option name Contempt type spin default 0 min -100 max 100");
+                            self.tx("option name OwnBook type check default true");
+                            self.tx("option name BookFile type string default <empty>");
+                            self.tx("option name BookPolicy type combo default best var best \
+                                 var frequency var score var uniform");
+                            self.tx(
+                                "option name BookSeed type spin default 0 min 0 max 2147483647",
+                            );
+                            self.tx("option name BookLearning type check default false");
+                            self.tx("option name BookLearnFile type string default <empty>");
                             self.tx("uciok");
                         }
                         "isready" => self.tx("readyok"),
+                        "setoption" => self.handle_setoption_command(input),
                         "position" => self.handle_position_command(input),
                         "ucinewgame" => self.handle_ucinewgame_command(),
                         "go" => self.handle_go_command(input),
                         "stop" => self.handle_stop_command(),
+                        "result" => self.handle_result_command(input),
                         "quit" => {
+                            self.learn_from_game(None);
                             break;
                         }
                         "d" => self.handle_debug_command(),
+                        "eval" => self.handle_eval_command(),
+                        "book" => self.handle_book_command(),
                         " " => {}
                         _ => {
                             let input = input.into_iter().collect::<Vec<_>>().join(" ");
@@ -78,17 +161,290 @@ impl UCI {
     }
 
     fn handle_debug_command(&mut self) {}
+
+    /// non-standard command printing every evaluation term of the current position
+    fn handle_eval_command(&mut self) {
+        self.tx(self.engine.eval_trace());
+        if let Some((key, value)) = self.engine.endgame_eval() {
+            self.tx(format!(
+                "Endgame {key}: {:.2} (white side)",
+                value as f64 / 100.0
+            ));
+        }
+        if let Some(value) = self.engine.nnue_eval() {
+            self.tx(format!(
+                "NNUE evaluation: {:.2} (white side)",
+                value as f64 / 100.0
+            ));
+        }
+    }
+    /// non-standard command printing the opening and the book moves of the current position
+    fn handle_book_command(&mut self) {
+        for line in self.book_lines() {
+            self.tx(line);
+        }
+    }
+
+    /// the output of the `book` command, each book move comes with its chance under the policy
+    fn book_lines(&self) -> Vec<String> {
+        let mut lines = vec![match self.engine.opening() {
+            Some(opening) => format!("info string opening {} {}", opening.eco, opening.name),
+            None => "info string opening unknown".to_string(),
+        }];
+        let chance = |chance: f64| format!("chance {:.1}%", 100.0 * chance);
+        let board = self.engine.board();
+        if let Some(book) = &self.polyglot_book {
+            let moves = book.moves(board);
+            let chances = self.book_policy.probabilities_weighted(&moves);
+            for ((mov, weight), (_, p)) in moves.iter().zip(chances) {
+                lines.push(format!("info string {mov} weight {weight} {}", chance(p)));
+            }
+            return lines;
+        }
+        let moves = self.opening_db.moves(board);
+        if moves.is_empty() {
+            lines.push("info string no book moves".to_string());
+        }
+        let chances = self.book_policy.probabilities(&moves);
+        for ((mov, stats), (_, p)) in moves.iter().zip(chances) {
+            let mut line = format!(
+                "info string {mov} {} games {} wins {} draws {} losses {}",
+                chance(p),
+                stats.games,
+                stats.wins,
+                stats.draws,
+                stats.losses
+            );
+            if let Some(score) = stats.score() {
+                line += &format!(" score {:.1}%", 100.0 * score);
+            }
+            if let Some(eval) = stats.exit_eval() {
+                line += &format!(" exit eval {:.2}", eval / 100.0);
+            }
+            if stats.is_bad() {
+                line += " avoided";
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
     fn handle_stop_command(&mut self) {
-        if let Some(mov) = self.engine.get_best_mov() {
-            self.engine.play_best_move();
-            let msg = format!("bestmove {mov}");
-            self.tx(msg);
+        if let Some(mov) = self.play_best_move() {
+            self.tx(format!("bestmove {mov}"));
         }
     }
 
-    fn handle_ucinewgame_command(&mut self) {
-        self.engine = Engine::new();
+    /// plays the move the search found and keeps it in the game for learning
+    fn play_best_move(&mut self) -> Option<ChessMove> {
+        let mov = self.engine.get_best_mov()?;
+        self.engine.play_best_move();
+        self.game_moves.push(mov);
+        Some(mov)
+    }
+
+    /// Keeps the game a `position` command gave for learning. Moves that do not go on from the
+    /// stored game start a new one, so the exit evaluation of the old game is dropped.
+    fn set_game(&mut self, start: Board, moves: &[ChessMove]) {
+        if start != self.game_start || !moves.starts_with(&self.game_moves) {
+            self.exit_eval = None;
+        }
+        self.game_start = start;
+        self.game_moves = moves.to_vec();
+    }
+
+    /// non-standard command giving the result of the game that just ended, `result 1-0`
+    fn handle_result_command(&mut self, mut args: VecDeque<&str>) {
+        match args.pop_front().and_then(GameResult::parse) {
+            Some(result) => self.learn_from_game(Some(result)),
+            None => self.tx("info string expected a result: 1-0, 0-1, 1/2-1/2 or *"),
+        }
+    }
+
+    /// Teaches the book the game that just ended when learning is on and saves it to the learn
+    /// file. Without a result the final position decides it, an unfinished game only adds the
+    /// evaluation at book exit.
+    fn learn_from_game(&mut self, result: Option<GameResult>) {
+        let moves = std::mem::take(&mut self.game_moves);
+        let exit_eval = self.exit_eval.take();
+        if !self.book_learning || moves.is_empty() {
+            return;
+        }
+        let board = self.engine.board();
+        let result = result.unwrap_or(match (board.status(), board.side_to_move()) {
+            (BoardStatus::Checkmate, Color::White) => GameResult::BlackWins,
+            (BoardStatus::Checkmate, Color::Black) => GameResult::WhiteWins,
+            (BoardStatus::Stalemate, _) => GameResult::Draw,
+            (BoardStatus::Ongoing, _) => GameResult::Unknown,
+        });
+
+        // the engine lets go of the book first so learning does not copy it
+        self.engine.add_opening_db(Arc::default());
+        let learned =
+            Arc::make_mut(&mut self.opening_db).learn(self.game_start, &moves, result, exit_eval);
         self.engine.add_opening_db(self.opening_db.clone());
+        if learned == 0 {
+            return;
+        }
+        self.tx(format!(
+            "info string learned {result} for {learned} book moves"
+        ));
+        if let Some(path) = &self.learn_file {
+            if let Err(err) = self.opening_db.save(path) {
+                self.tx(format!(
+                    "info string could not save the book to {path}: {err}"
+                ));
+            }
+        }
+    }
+
+    fn handle_ucinewgame_command(&mut self) {
+        self.learn_from_game(None);
+        self.engine = self.with_options(Engine::new());
+    }
+
+    /// setoption name <id> [value <x>]
+    fn handle_setoption_command(&mut self, mut args: VecDeque<&str>) {
+        if args.pop_front() != Some("name") {
+            return;
+        }
+        let mut name = Vec::new();
+        while let Some(part) = args.pop_front() {
+            if part == "value" {
+                break;
+            }
+            name.push(part);
+        }
+        let name = name.join(" ");
+        let value = args.into_iter().collect::<Vec<_>>().join(" ");
+
+        match name.to_lowercase().as_str() {
+            "evalfile" => self.set_eval_file(&value),
+            "usennue" => {
+                self.use_nnue = value.eq_ignore_ascii_case("true");
+                if self.use_nnue && self.network.is_none() {
+                    self.tx("info string no NNUEFile loaded, using the classic evaluation");
+                }
+                self.engine.set_evaluator(self.evaluator());
+            }
+            "nnuefile" => self.set_nnue_file(&value),
+            "syzygypath" => self.set_syzygy_path(&value),
+            "ownbook" => {
+                self.own_book = value.eq_ignore_ascii_case("true");
+                self.engine.set_own_book(self.own_book);
+            }
+            "bookfile" => self.set_book_file(&value),
+            "booklearning" => self.book_learning = value.eq_ignore_ascii_case("true"),
+            "booklearnfile" => self.set_learn_file(&value),
+            "bookpolicy" => match value.parse::<BookPolicy>() {
+                Ok(policy) => {
+                    self.book_policy = policy;
+                    self.engine.set_book_policy(policy);
+                }
+                Err(err) => self.tx(format!("info string {err}")),
+            },
+            // 0 leaves the book moves to chance
+            "bookseed" => match value.parse::<u64>() {
+                Ok(0) => self.engine.set_book_rng(StdRng::from_entropy()),
+                Ok(seed) => self.engine.set_book_rng(StdRng::seed_from_u64(seed)),
+                Err(err) => self.tx(format!("info string bad book seed {value}: {err}")),
+            },
+            "contempt" => match value.parse::<isize>() {
+                Ok(contempt) => {
+                    self.contempt = contempt.clamp(-100, 100);
+                    self.engine.set_contempt(self.contempt);
+                }
+                Err(err) => self.tx(format!("info string bad contempt {value}: {err}")),
+            },
+            _ => self.tx(format!("info string unknown option {name}")),
+        }
+    }
+
+    fn set_eval_file(&mut self, path: &str) {
+        if path.is_empty() || path == "<empty>" {
+            self.eval_params = EvalParams::default();
+        } else {
+            match EvalParams::load(path) {
+                Ok(params) => self.eval_params = params,
+                Err(err) => {
+                    self.tx(format!("info string {err}"));
+                    return;
+                }
+            }
+        }
+        self.engine.set_eval_params(self.eval_params.clone());
+    }
+
+    fn set_nnue_file(&mut self, path: &str) {
+        if path.is_empty() || path == "<empty>" {
+            self.network = None;
+        } else {
+            match Network::load(path) {
+                Ok(net) => self.network = Some(Arc::new(net)),
+                Err(err) => {
+                    self.tx(format!("info string {err}"));
+                    return;
+                }
+            }
+        }
+        self.engine.set_evaluator(self.evaluator());
+    }
+
+    fn set_syzygy_path(&mut self, path: &str) {
+        if path.is_empty() || path == "<empty>" {
+            self.tablebases = None;
+        } else {
+            match Tablebases::new(path) {
+                Ok(tablebases) => {
+                    self.tx(format!("info string found {} tablebases", tablebases.len()));
+                    self.tablebases = Some(Arc::new(tablebases));
+                }
+                Err(err) => {
+                    self.tx(format!("info string {err}"));
+                    return;
+                }
+            }
+        }
+        self.engine.set_tablebases(self.tablebases.clone());
+    }
+
+    /// a Polyglot book replaces the built-in one, an empty path goes back to it
+    fn set_book_file(&mut self, path: &str) {
+        if path.is_empty() || path == "<empty>" {
+            self.polyglot_book = None;
+        } else {
+            match PolyglotBook::load(path) {
+                Ok(book) => {
+                    self.tx(format!("info string book has {} entries", book.len()));
+                    self.polyglot_book = Some(Arc::new(book));
+                }
+                Err(err) => {
+                    self.tx(format!("info string {err}"));
+                    return;
+                }
+            }
+        }
+        self.engine.set_polyglot_book(self.polyglot_book.clone());
+    }
+
+    /// Learning goes on from the book saved at `path` when there is one, it is created after
+    /// the first learned game otherwise.
+    fn set_learn_file(&mut self, path: &str) {
+        if path.is_empty() || path == "<empty>" {
+            self.learn_file = None;
+            return;
+        }
+        if Path::new(path).exists() {
+            match OpeningDatabase::load(path) {
+                Ok(db) => self.add_db(db),
+                Err(err) => {
+                    // keep the file as it is rather than overwrite it with the next game
+                    self.tx(format!("info string {err}"));
+                    return;
+                }
+            }
+        }
+        self.learn_file = Some(path.to_string());
     }
 
     fn handle_go_command(&mut self, mut args: VecDeque<&str>) {
@@ -102,12 +458,13 @@ impl UCI {
         };
 
         let now = Instant::now().add(Duration::from_secs(1));
-        self.engine.search_iterative_deeping(now);
+        let eval = self.engine.search_iterative_deeping(now);
+        if !self.engine.is_book_move() && self.exit_eval.is_none() {
+            self.exit_eval = Some(white_centipawns(eval, self.engine.board().side_to_move()));
+        }
 
-        if let Some(mov) = self.engine.get_best_mov() {
-            let msg = format!("bestmove {mov}");
-            self.engine.play_best_move();
-            self.tx(msg);
+        if let Some(mov) = self.play_best_move() {
+            self.tx(format!("bestmove {mov}"));
         }
     }
 
@@ -140,19 +497,35 @@ impl UCI {
                     }
                 }
                 let fen = fen_part.join(" ");
-                self.engine = Engine::from_str(&fen).unwrap();
+                self.engine = self.with_options(Engine::from_str(&fen).unwrap());
 
                 let moves = parse_moves(cmd);
-                if let Some(mov) = moves.iter().last() {
-                    println!("info playing last move {mov}");
-                    self.engine.play_move(*mov);
+                self.set_game(*self.engine.board(), &moves);
+                for mov in moves {
+                    self.engine.play_move(mov);
                 }
             }
             "startpos" => {
                 let moves = parse_moves(cmd);
-                if let Some(mov) = moves.iter().last() {
-                    println!("info playing last move {mov}");
-                    self.engine.play_move(*mov);
+                self.set_game(Board::default(), &moves);
+                let played = self.engine.book_cursor().map(|cursor| cursor.path());
+                match played {
+                    // the usual case, the reply to the move the engine just played
+                    Some(played)
+                        if moves.len() == played.len() + 1 && moves.starts_with(played) =>
+                    {
+                        let mov = moves[played.len()];
+                        println!("info playing last move {mov}");
+                        self.engine.play_move(mov);
+                    }
+                    Some(played) if played == moves => {}
+                    // a new game or moves taken back, the line is played again from the start
+                    _ => {
+                        self.engine = self.with_options(Engine::new());
+                        for mov in moves {
+                            self.engine.play_move(mov);
+                        }
+                    }
                 }
             }
             _ => {
@@ -167,8 +540,90 @@ impl UCI {
     }
 }
 
+/// a search result from the side to move in centipawns for White, mates as a large advantage
+fn white_centipawns(eval: EvaluationValue, side: Color) -> isize {
+    let eval = match eval {
+        EvaluationValue::Eval(eval) => eval,
+        EvaluationValue::CheckMate(true) => isize::MAX,
+        EvaluationValue::CheckMate(false) => -isize::MAX,
+    };
+    match side {
+        Color::White => eval,
+        Color::Black => -eval,
+    }
+}
+
 impl Default for UCI {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::line;
+
+    #[test]
+    fn book_chances_add_up() {
+        let mut db = OpeningDatabase::new();
+        for game in [
+            "1. e4 e5 2. Nf3 1-0",
+            "1. e4 c5 0-1",
+            "1. d4 d5 1/2-1/2",
+            "1. c4 e5 0-1",
+            "1. e4 e6 1-0",
+        ] {
+            db.add_png(game).unwrap();
+        }
+        let mut uci = UCI::new();
+        uci.add_db(db);
+
+        for policy in ["best", "frequency", "score", "uniform"] {
+            let option = format!("name BookPolicy value {policy}");
+            uci.handle_setoption_command(option.split(' ').collect());
+            let chances = uci
+                .book_lines()
+                .iter()
+                .filter_map(|line| line.split("chance ").nth(1))
+                .map(|rest| rest.split('%').next().unwrap().parse::<f64>().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(chances.len(), 3, "{policy}");
+            let total = chances.iter().sum::<f64>();
+            assert!((total - 100.0).abs() < 0.2, "{policy}: {chances:?}");
+        }
+        // uniform spreads the chances, best always plays e4
+        assert!(uci
+            .book_lines()
+            .iter()
+            .all(|line| !line.contains("chance 100.0%")));
+        uci.handle_setoption_command("name BookPolicy value best".split(' ').collect());
+        assert!(uci.book_lines()[1..]
+            .iter()
+            .any(|line| line.contains("e2e4 chance 100.0%")));
+    }
+
+    #[test]
+    fn game_follows_the_engine_and_restarts() {
+        let mut db = OpeningDatabase::new();
+        db.add_png("1. e4 e5 2. Nf3 1-0").unwrap();
+        let mut uci = UCI::new();
+        uci.add_db(db);
+        let position = |uci: &mut UCI, moves: &str| {
+            uci.handle_position_command(format!("startpos moves {moves}").split(' ').collect())
+        };
+
+        // the book answers at once and its move becomes part of the game
+        position(&mut uci, "e2e4 e7e5");
+        uci.handle_go_command(VecDeque::new());
+        assert_eq!(uci.game_moves, line(&["e2e4", "e7e5", "g1f3"]));
+
+        uci.exit_eval = Some(30);
+        position(&mut uci, "e2e4 e7e5 g1f3 b8c6");
+        assert_eq!(uci.exit_eval, Some(30));
+        // another game without ucinewgame
+        position(&mut uci, "d2d4");
+        assert_eq!(uci.game_moves, line(&["d2d4"]));
+        assert_eq!(uci.exit_eval, None);
+    }
+}